@@ -0,0 +1,296 @@
+use crate::lexer::Span;
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+thread_local! {
+    /// Location of the built-in function call being executed on this thread, for errors raised without a span.
+    static CALL_SPAN: Cell<Option<Span>> = const { Cell::new(None) };
+}
+/// Path and contents of every file read and not removed, by `Span::file`, so errors show the file they come from.
+static FILES: Mutex<BTreeMap<usize, (String, String)>> = Mutex::new(BTreeMap::new());
+/// The index of the next file added, never reused so a span into a removed file does not point into another.
+static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+/// An error with the source location it points at, an optional hint and an optional code naming the kind of error.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Option<Span>,
+    pub hint: Option<String>,
+    /// Such as "step-limit", for errors a host may want to tell apart.
+    pub code: Option<&'static str>
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>) -> Self {
+        Diagnostic { message: message.into(), span: None, hint: None, code: None }
+    }
+    pub fn at(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+    pub fn with_hint(mut self, hint: Option<String>) -> Self {
+        self.hint = hint;
+        self
+    }
+    pub fn with_code(mut self, code: &'static str) -> Self {
+        self.code = Some(code);
+        self
+    }
+}
+
+/// How diagnostics are written, `calc_lang --error-format color|plain|json`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorFormat {
+    Color, Plain, Json
+}
+
+/// Stops running the program with a diagnostic, which `Engine::run` catches and returns.
+pub fn raise(diagnostic: Diagnostic) -> ! {
+    std::panic::panic_any(diagnostic)
+}
+
+/// Stops running the program with several diagnostics, such as the syntax errors of an imported file.
+pub fn raise_all(diagnostics: Vec<Diagnostic>) -> ! {
+    std::panic::panic_any(diagnostics)
+}
+
+/// Remembers a file read, so errors pointing into it can show its source.
+///
+/// # Returns
+/// The index of the file, for `Span::file`.
+pub fn add_file(path: &str, contents: &str) -> usize {
+    let file = NEXT_FILE.fetch_add(1, Ordering::SeqCst);
+    FILES.lock().unwrap().insert(file, (path.to_string(), contents.to_string()));
+    file
+}
+
+/// Returns the path and contents of a file remembered with `add_file`.
+pub fn get_file(file: usize) -> Option<(String, String)> {
+    FILES.lock().unwrap().get(&file).cloned()
+}
+
+/// Forgets a file remembered with `add_file` once no error will be rendered pointing into it, as `Engine::run` does
+/// with the files a run imports.
+pub fn remove_file(file: usize) {
+    FILES.lock().unwrap().remove(&file);
+}
+
+/// Runs a built-in function call, so that errors it raises as plain messages point at the call.
+///
+/// # Arguments
+/// * `span` - The span of the call.
+/// * `call` - The call to run.
+pub fn within<T>(span: Span, call: impl FnOnce() -> T) -> T {
+    let previous = CALL_SPAN.replace(Some(span));
+    let result = call();
+    // only restored on success, so a failing call keeps its span
+    CALL_SPAN.set(previous);
+    result
+}
+
+/// Converts a caught panic into diagnostics, plain messages and diagnostics without a span pointing at the call that raised them.
+/// Called on the thread that caught it, which then starts again outside of any call.
+pub fn from_panic(payload: Box<dyn Any + Send>) -> Vec<Diagnostic> {
+    let call_span = CALL_SPAN.take();
+    let payload = match payload.downcast::<Diagnostic>() {
+        Ok(diagnostic) => match (diagnostic.span, call_span) {
+            (None, Some(span)) => return vec![diagnostic.at(span)],
+            _ => return vec![*diagnostic],
+        },
+        Err(payload) => payload,
+    };
+    let payload = match payload.downcast::<Vec<Diagnostic>>() {
+        Ok(diagnostics) => return *diagnostics,
+        Err(payload) => payload,
+    };
+    let message = match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().map_or("Unknown error".to_string(), |x| x.to_string()),
+    };
+    let diagnostic = Diagnostic::new(message);
+    match call_span {
+        Some(span) => vec![diagnostic.at(span)],
+        None => vec![diagnostic],
+    }
+}
+
+/// Suggests the closest name to a misspelled one.
+///
+/// # Arguments
+/// * `name` - The name that was not found.
+/// * `candidates` - The names that exist.
+///
+/// # Returns
+/// A hint such as "did you mean `sin`?", if a name is close enough, with fewer edits than the name has letters so
+/// a one-letter name is never matched.
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let length = name.chars().count();
+    let limit = (length / 3).max(1);
+    candidates.into_iter()
+        .filter(|x| *x != name)
+        .map(|x| (edit_distance(name, x), x))
+        .filter(|(distance, _)| *distance <= limit && *distance < length)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, x)| format!("did you mean `{}`?", x))
+}
+
+/// Counts the insertions, deletions and substitutions needed to turn one name into another.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, x) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, y) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(x != *y);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Renders a diagnostic with the source line it points at, the span underlined.
+///
+/// # Arguments
+/// * `diagnostic` - The diagnostic to render.
+/// * `format` - Whether to render coloured text, plain text or a line of JSON.
+pub fn render(diagnostic: &Diagnostic, format: ErrorFormat) -> String {
+    // errors without a location belong to the file that was run
+    let files = FILES.lock().unwrap();
+    let file = diagnostic.span.map_or(0, |x| x.file);
+    let (path, source) = files.get(&file).map_or(("", ""), |(path, source)| (path.as_str(), source.as_str()));
+    if format == ErrorFormat::Json {
+        return render_json(diagnostic, path);
+    }
+    let paint = |text: &str, code: &str| {
+        if format == ErrorFormat::Color { format!("\x1b[{}m{}\x1b[0m", code, text) } else { text.to_string() }
+    };
+
+    let error = match diagnostic.code {
+        Some(code) => format!("error[{}]", code),
+        None => "error".to_string(),
+    };
+    let mut output = format!("{}: {}", paint(&error, "1;31"), paint(&diagnostic.message, "1"));
+    if let Some(span) = diagnostic.span {
+        let line_text = source.split('\n').nth((span.line as usize).saturating_sub(1)).unwrap_or("").trim_end_matches('\r');
+        let gutter = " ".repeat(span.line.to_string().len());
+        // keep tabs so the carets line up with the source
+        let indent: String = line_text.chars().take(span.column.saturating_sub(1)).map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        let line_start = span.offset.saturating_sub(line_text.char_indices().nth(span.column.saturating_sub(1)).map_or(line_text.len(), |(i, _)| i));
+        let end = (span.offset + span.length).min(line_start + line_text.len());
+        let width = source.get(span.offset..end).map_or(1, |x| x.chars().count()).max(1);
+
+        output += &format!("\n{}{} {}:{}:{}", gutter, paint("-->", "1;34"), path, span.line, span.column);
+        output += &format!("\n{} {}", gutter, paint("|", "1;34"));
+        output += &format!("\n{} {} {}", paint(&span.line.to_string(), "1;34"), paint("|", "1;34"), line_text);
+        output += &format!("\n{} {} {}{}", gutter, paint("|", "1;34"), indent, paint(&"^".repeat(width), "1;31"));
+        if let Some(hint) = &diagnostic.hint {
+            output += &format!("\n{} {} {}", gutter, paint("=", "1;34"), paint(&format!("help: {}", hint), "1;36"));
+        }
+    }
+    else if let Some(hint) = &diagnostic.hint {
+        output += &format!("\n  {} {}", paint("=", "1;34"), paint(&format!("help: {}", hint), "1;36"));
+    }
+    output
+}
+
+/// Renders several diagnostics, ending with how many errors there were when there is more than one.
+///
+/// # Arguments
+/// * `diagnostics` - The diagnostics to render.
+/// * `path` - The path of the file that was run.
+/// * `format` - Whether to render coloured text, plain text or lines of JSON.
+pub fn render_all(diagnostics: &[Diagnostic], path: &str, format: ErrorFormat) -> String {
+    let rendered: Vec<String> = diagnostics.iter().map(|x| render(x, format)).collect();
+    if format == ErrorFormat::Json {
+        return rendered.join("\n");
+    }
+    let mut output = rendered.join("\n\n");
+    if diagnostics.len() > 1 {
+        let summary = Diagnostic::new(format!("could not run '{}' due to {} errors", path, diagnostics.len()));
+        output += &format!("\n\n{}", render(&summary, format));
+    }
+    output
+}
+
+/// Renders a diagnostic as one line of JSON, for editors and other tools.
+fn render_json(diagnostic: &Diagnostic, path: &str) -> String {
+    let mut fields = vec![
+        "\"severity\":\"error\"".to_string(),
+        format!("\"message\":{}", json_string(&diagnostic.message)),
+        format!("\"file\":{}", json_string(path)),
+    ];
+    if let Some(span) = diagnostic.span {
+        fields.push(format!("\"line\":{},\"column\":{},\"offset\":{},\"length\":{}", span.line, span.column, span.offset, span.length));
+    }
+    if let Some(hint) = &diagnostic.hint {
+        fields.push(format!("\"hint\":{}", json_string(hint)));
+    }
+    if let Some(code) = diagnostic.code {
+        fields.push(format!("\"code\":{}", json_string(code)));
+    }
+    format!("{{{}}}", fields.join(","))
+}
+
+/// Quotes and escapes text as a JSON string.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            '\n' => quoted += "\\n",
+            '\r' => quoted += "\\r",
+            '\t' => quoted += "\\t",
+            c if (c as u32) < 0x20 => quoted += &format!("\\u{:04x}", c as u32),
+            c => quoted.push(c),
+        }
+    }
+    quoted + "\""
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::run_script;
+    use crate::limits::Limits;
+
+    #[test]
+    fn suggestions_need_fewer_edits_than_letters() {
+        assert_eq!(suggest("sine", ["sin", "sign", "cos"]), Some("did you mean `sin`?".to_string()));
+        assert_eq!(suggest("x", ["y", "e"]), None);
+        assert_eq!(suggest("ab", ["ax", "abs"]), Some("did you mean `ax`?".to_string()));
+        assert_eq!(suggest("integrat", ["integrate", "intercept"]), Some("did you mean `integrate`?".to_string()));
+        assert_eq!(suggest("max", ["max"]), None);
+        // functions are only suggested for calls and variables for names
+        let errors = run_script("displayln(abz)", "", Limits::default()).unwrap_err();
+        assert_eq!(errors[0].hint, None);
+        let errors = run_script("displayln(pii)", "", Limits::default()).unwrap_err();
+        assert_eq!(errors[0].hint, Some("did you mean `pi`?".to_string()));
+        let errors = run_script("displayln(abz(1))", "", Limits::default()).unwrap_err();
+        assert_eq!(errors[0].hint, Some("did you mean `abs`?".to_string()));
+    }
+
+    #[test]
+    fn rendering_points_at_the_span() {
+        let source = "1 + 2\n\tsqrt(ab)\n";
+        let file = add_file("render.calc", source);
+        let span = Span { offset: 12, length: 2, line: 2, column: 7, file };
+        let diagnostic = Diagnostic::new("Variable 'ab' does not exist").at(span).with_hint(Some("did you mean `abs`?".to_string()));
+        assert_eq!(render(&diagnostic, ErrorFormat::Plain), "error: Variable 'ab' does not exist\n \
+            --> render.calc:2:7\n  |\n2 | \tsqrt(ab)\n  | \t     ^^\n  = help: did you mean `abs`?");
+        assert_eq!(render(&diagnostic.clone().with_code("step-limit"), ErrorFormat::Json), "{\"severity\":\"error\",\
+            \"message\":\"Variable 'ab' does not exist\",\"file\":\"render.calc\",\"line\":2,\"column\":7,\"offset\":12,\
+            \"length\":2,\"hint\":\"did you mean `abs`?\",\"code\":\"step-limit\"}");
+        // a span before the start of a line or a removed file renders without panicking
+        let before = Span { offset: 0, length: 1, line: 0, column: 0, file };
+        assert!(render(&Diagnostic::new("at the start").at(before), ErrorFormat::Plain).contains("render.calc:0:0"));
+        remove_file(file);
+        assert_eq!(get_file(file), None);
+        assert!(render(&diagnostic, ErrorFormat::Plain).contains(" --> :2:7"));
+    }
+}
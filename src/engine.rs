@@ -0,0 +1,306 @@
+use crate::diagnostic::{from_panic, Diagnostic};
+use crate::interpreter::{get_goto_function, get_pre_defined_functions, interpret_with, Context, NativeFunction, NativeFunctions, Value};
+use crate::import::Imports;
+use crate::format::get_precision_function;
+use crate::io::{get_io_functions, Io, StdIo};
+use crate::limits::{Counter, Limits};
+use crate::numeric::{get_numeric_functions, NumericFunctions};
+use crate::parser::Expr;
+use crate::prelude::{self, Prelude};
+use crate::random::get_random_functions;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::Once;
+
+/// The result of a function registered with `Engine::register_fn`, the error being the message reported at the call.
+pub type Result<T> = std::result::Result<T, String>;
+/// The body of a native function, a built-in or one registered with `Engine::register_fn`.
+pub type NativeFn = Box<dyn Fn(&[Value]) -> Result<Value>>;
+
+thread_local! {
+    /// Whether this thread is running a script, whose errors the panic hook leaves to `Engine::run` to return.
+    static IN_RUN: Cell<bool> = const { Cell::new(false) };
+}
+/// Installs the panic hook once for every engine.
+static HOOK: Once = Once::new();
+
+/// Wraps the panic hook so it is silent while a thread runs a script, and calls the host's hook otherwise.
+fn install_hook() {
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !IN_RUN.get() {
+                previous(info);
+            }
+        }));
+    });
+}
+
+/// Marks the thread as running a script until dropped, restoring what it was for a run inside a host function.
+struct Running {
+    was: bool
+}
+
+impl Running {
+    fn start() -> Self {
+        install_hook();
+        Running { was: IN_RUN.replace(true) }
+    }
+}
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        IN_RUN.set(self.was);
+    }
+}
+
+/// Runs scripts with the built-in functions and the ones a host program registers.
+pub struct Engine {
+    functions: NativeFunctions,
+    numeric_functions: NumericFunctions,
+    /// The names of the functions registered by the host, which `set_io` leaves in place.
+    registered: HashSet<String>,
+    io: Rc<RefCell<dyn Io>>,
+    /// The marker `goto` jumps to, shared with it.
+    jump: Rc<Cell<Option<i32>>>,
+    /// The random number generator the random functions draw from, shared with them.
+    rng: Rc<RefCell<StdRng>>,
+    /// The significant digits numbers are displayed with, shared with the display functions and `precision()`.
+    precision: Rc<Cell<usize>>,
+    /// Directories searched for imported files, after the directory of the importing file.
+    search_paths: Vec<PathBuf>,
+    limits: Limits,
+    /// Whether scripts start with the prelude, and what it defines once run.
+    prelude: Prelude
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    /// Creates an engine with the built-in functions, displaying to standard output and reading from standard input.
+    pub fn new() -> Self {
+        let mut functions: NativeFunctions = get_pre_defined_functions().into_iter().map(|x| (x.name.clone(), NativeFunction::from(x))).collect();
+        let numeric_functions = get_numeric_functions().into_iter().map(|x| (x.name.clone(), x)).collect();
+        let jump = Rc::new(Cell::new(None));
+        functions.insert("goto".to_string(), get_goto_function(&jump));
+        let rng = Rc::new(RefCell::new(StdRng::from_entropy()));
+        functions.extend(get_random_functions(&rng).into_iter().map(|x| (x.name.clone(), x)));
+        let precision = Rc::new(Cell::new(0));
+        functions.insert("precision".to_string(), get_precision_function(&precision));
+        let io: Rc<RefCell<dyn Io>> = Rc::new(RefCell::new(StdIo));
+        let mut engine = Engine { functions, numeric_functions, registered: HashSet::new(), io: io.clone(), jump, rng, precision, search_paths: vec![], limits: Limits::default(), prelude: Prelude::new(true) };
+        engine.set_io(io);
+        engine
+    }
+
+    /// Sets where `display`, `dac`, `read` and `clear` write and read, such as a `MemoryIo` the host keeps a handle to.
+    /// A function registered with `register_fn` under one of their names stays in place of it.
+    pub fn set_io(&mut self, io: Rc<RefCell<dyn Io>>) {
+        for function in get_io_functions(&io, &self.precision) {
+            if !self.registered.contains(&function.name) {
+                self.functions.insert(function.name.clone(), function);
+            }
+        }
+        self.io = io;
+    }
+
+    /// Returns where the engine writes and reads.
+    pub fn io(&self) -> Rc<RefCell<dyn Io>> {
+        self.io.clone()
+    }
+
+    /// Adds a function scripts can call, replacing any built-in or prelude function with the same name, including
+    /// the I/O functions of a later `set_io`. A function the script defines itself is called in its place.
+    ///
+    /// # Arguments
+    /// * `name` - The name scripts call it by, in lowercase letters.
+    /// * `arity` - The number of values it takes, -1 for any number.
+    /// * `function` - The function, which may capture state of the host.
+    pub fn register_fn(&mut self, name: &str, arity: i32, function: NativeFn) {
+        let native = NativeFunction { name: name.to_string(), parameters: arity, execute: function };
+        self.functions.insert(name.to_string(), native);
+        self.registered.insert(name.to_string());
+        // the prelude's constants may be calculated with it
+        self.prelude = Prelude::new(self.prelude.is_enabled());
+    }
+
+    /// Reseeds the random number generator, seeded from the operating system when the engine is created, so every
+    /// following draw is reproducible. Scripts reseed it with `seed()`.
+    pub fn seed(&mut self, seed: u64) {
+        *self.rng.borrow_mut() = StdRng::seed_from_u64(seed);
+    }
+
+    /// Sets the significant digits numbers are displayed with, 0 to display as many as needed to round-trip, as
+    /// scripts do with `precision()`.
+    pub fn set_precision(&mut self, digits: usize) {
+        self.precision.set(digits);
+    }
+
+    /// Adds a directory to search for imported files not found next to the file importing them, after the ones
+    /// already added.
+    pub fn add_search_path(&mut self, directory: impl Into<PathBuf>) {
+        self.search_paths.push(directory.into());
+    }
+
+    /// Sets whether scripts and the files they import start with the constants and functions of the prelude, which
+    /// they do unless turned off here.
+    pub fn set_prelude(&mut self, enabled: bool) {
+        self.prelude = Prelude::new(enabled);
+    }
+
+    /// Sets the limits on the steps, call depth, set size and time of each script run, past which it stops with an error.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Runs parsed lines, starting with the functions and constants of the prelude, unless turned off with
+    /// `set_prelude`, and the default variables.
+    ///
+    /// The source of the files the script imports is forgotten once it has run, apart from those its errors point
+    /// into, while the script's own file is left for the host to remove with `diagnostic::remove_file`.
+    ///
+    /// Errors are panics caught here, kept quiet by a panic hook wrapping the host's, which still runs for panics
+    /// outside of a run. A host built with `panic = "abort"` cannot catch them, so its process ends at the first error.
+    ///
+    /// # Returns
+    /// The error that stopped the script, if any.
+    pub fn run(&self, lines: Vec<Option<Expr>>) -> std::result::Result<(), Vec<Diagnostic>> {
+        let _running = Running::start();
+        self.jump.set(None);
+        let imports = Imports::new(&self.search_paths);
+        let last_error = Cell::new(0.0);
+        let context = Context {
+            functions: &self.functions,
+            numeric_functions: &self.numeric_functions,
+            registered: &self.registered,
+            jump: &self.jump,
+            imports: &imports,
+            prelude: &self.prelude,
+            last_error: &last_error,
+            counter: Counter::new(self.limits)
+        };
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let (defined_functions, variables) = prelude::session(&context);
+            interpret_with(lines, defined_functions, variables, &context);
+        })).map_err(from_panic);
+        imports.remove_files(result.as_ref().err().map_or(&[], |x| x.as_slice()));
+        result
+    }
+}
+
+/// Runs a script reading the lines of `input`, returning everything it displayed or the errors that stopped it.
+#[cfg(test)]
+pub(crate) fn run_script(script: &str, input: &str, limits: Limits) -> std::result::Result<String, Vec<Diagnostic>> {
+    use crate::diagnostic::add_file;
+    use crate::io::MemoryIo;
+    use crate::lexer::get_lexer_lines;
+    use crate::parser::parse;
+
+    let (lines, mut diagnostics) = get_lexer_lines(script, add_file("<test>", script));
+    let (lines, parser_diagnostics) = parse(lines);
+    diagnostics.extend(parser_diagnostics);
+    diagnostics.sort_by_key(|x| x.span.map(|x| x.offset));
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+    let io = Rc::new(RefCell::new(MemoryIo::new(input)));
+    let mut engine = Engine::new();
+    engine.set_io(io.clone());
+    engine.set_limits(limits);
+    engine.run(lines)?;
+    let output = io.borrow().output.clone();
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::add_file;
+    use crate::io::MemoryIo;
+    use crate::lexer::get_lexer_lines;
+    use crate::parser::parse;
+
+    #[test]
+    fn host_functions_are_kept_over_set_io() {
+        let mut engine = Engine::new();
+        engine.register_fn("display", -1, Box::new(|_| Ok(Value::Number(7.0))));
+        let io = Rc::new(RefCell::new(MemoryIo::new("")));
+        engine.set_io(io.clone());
+        let script = "displayln(display(1))";
+        let (lines, _) = get_lexer_lines(script, add_file("<test>", script));
+        engine.run(parse(lines).0).unwrap();
+        assert_eq!(io.borrow().output, "7\n");
+    }
+
+    #[test]
+    fn host_functions_are_called_over_the_prelude() {
+        let mut engine = Engine::new();
+        engine.register_fn("max", 2, Box::new(|_| Ok(Value::Number(7.0))));
+        let io = Rc::new(RefCell::new(MemoryIo::new("")));
+        engine.set_io(io.clone());
+        let script = "displayln(max(1, 2))\nmax(X, Y) => X + Y\ndisplayln(max(1, 2))";
+        let (lines, _) = get_lexer_lines(script, add_file("<test>", script));
+        engine.run(parse(lines).0).unwrap();
+        // a function the script defines itself is still called over the host's
+        assert_eq!(io.borrow().output, "7\n3\n");
+    }
+
+    #[test]
+    fn panics_are_quiet_only_while_running() {
+        let mut engine = Engine::new();
+        engine.register_fn("running", 0, Box::new(|_| Ok(Value::Number(f64::from(u8::from(IN_RUN.get()))))));
+        let io = Rc::new(RefCell::new(MemoryIo::new("")));
+        engine.set_io(io.clone());
+        for script in ["displayln(running())", "displayln(running())\nnothing(1)"] {
+            let (lines, _) = get_lexer_lines(script, add_file("<test>", script));
+            let _ = engine.run(parse(lines).0);
+            assert!(!IN_RUN.get());
+        }
+        assert_eq!(io.borrow().output, "1\n1\n");
+    }
+
+    #[test]
+    fn the_prelude_is_an_option_of_each_engine() {
+        let script = "displayln(abs(-2))";
+        let (lines, _) = get_lexer_lines(script, add_file("<test>", script));
+        let lines = parse(lines).0;
+        let mut disabled = Engine::new();
+        disabled.set_prelude(false);
+        disabled.set_io(Rc::new(RefCell::new(MemoryIo::new(""))));
+        let errors = disabled.run(lines.clone()).unwrap_err();
+        assert_eq!(errors[0].message, "Function 'abs' not defined");
+        // an engine with the prelude is unaffected, before and after it has run once
+        for _ in 0..2 {
+            assert_eq!(run_script(script, "", Limits::default()), Ok("2\n".to_string()));
+            assert!(disabled.run(lines.clone()).is_err());
+        }
+    }
+
+    #[test]
+    fn markers_belong_to_their_script() {
+        assert_eq!(run_script("$5\nI + 1 -> I\nI < 3: goto(5)\ndisplayln(I)", "", Limits::default()), Ok("3\n".to_string()));
+        let errors = run_script("goto(5)", "", Limits::default()).unwrap_err();
+        assert_eq!(errors[0].message, "Marker $5 does not exist in function: goto");
+    }
+
+    #[test]
+    fn goto_resumes_after_a_marker_sharing_its_line() {
+        assert_eq!(run_script("1 -> A, $0\nA + 1 -> A\nA < 3: goto(0)\ndisplayln(A)", "", Limits::default()), Ok("3\n".to_string()));
+    }
+
+    #[test]
+    fn errors_are_returned() {
+        let errors = run_script("displayln(1)\nnothing(2)", "", Limits::default()).unwrap_err();
+        assert_eq!(errors[0].message, "Function 'nothing' not defined");
+        assert_eq!(errors[0].span.map(|x| x.line), Some(2));
+    }
+}
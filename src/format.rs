@@ -0,0 +1,292 @@
+use crate::interpreter::{NativeFunction, PreDefinedFunction, Value};
+use std::cell::Cell;
+use std::rc::Rc;
+
+/// Returns a vector of functions formatting numbers as text.
+pub fn get_format_functions() -> Vec<PreDefinedFunction> {
+    vec![
+        PreDefinedFunction {
+            name: "format".to_string(),
+            parameters: 2,
+            execute: |v| {
+                match &v[1] {
+                    Value::Text(pattern) => Value::Text(format_pattern(v[0].number(), pattern)),
+                    other => panic!("Expected a text pattern, got {} in function: format", other.kind()),
+                }
+            }
+        },
+        PreDefinedFunction {
+            name: "fixed".to_string(),
+            parameters: 2,
+            execute: |v| {
+                let digits = count(v[1].number(), "fixed");
+                Value::Text(format!("{:.*}", digits, v[0].number()))
+            }
+        },
+        PreDefinedFunction {
+            name: "sigfig".to_string(),
+            parameters: 2,
+            execute: |v| {
+                let digits = count(v[1].number(), "sigfig").max(1);
+                Value::Text(significant(v[0].number(), digits))
+            }
+        },
+        PreDefinedFunction {
+            name: "sci".to_string(),
+            parameters: 2,
+            execute: |v| {
+                let digits = count(v[1].number(), "sci");
+                Value::Text(format!("{:.*e}", digits, v[0].number()))
+            }
+        },
+        PreDefinedFunction {
+            name: "eng".to_string(),
+            parameters: 2,
+            execute: |v| {
+                let digits = count(v[1].number(), "eng");
+                Value::Text(engineering(v[0].number(), digits))
+            }
+        }
+    ]
+}
+
+/// Returns `precision`, which sets the significant digits an engine displays numbers with, shared with its display
+/// functions.
+pub fn get_precision_function(precision: &Rc<Cell<usize>>) -> NativeFunction {
+    let precision = precision.clone();
+    NativeFunction {
+        name: "precision".to_string(),
+        parameters: 1,
+        execute: Box::new(move |v| {
+            precision.set(count(v[0].number(), "precision"));
+            Ok(Value::Number(0.0))
+        })
+    }
+}
+
+/// Checks that an argument is a whole number of digits.
+fn count(digits: f64, name: &str) -> usize {
+    if digits < 0.0 || digits.fract() != 0.0 {
+        panic!("Expected a whole number of digits, got {} in function: {}", digits, name);
+    }
+    digits as usize
+}
+
+/// Formats a number for display.
+///
+/// # Arguments
+/// * `x` - The number to format.
+/// * `precision` - The significant digits to round to, or 0 to display as many as needed to round-trip. A number
+///   with more whole digits than that is written with an exponent, such as `1.23e5`, so no digit shown is made up.
+pub fn format_number(x: f64, precision: usize) -> String {
+    if precision == 0 || !x.is_finite() {
+        return x.to_string();
+    }
+    // the exponent once rounded, which can carry 999.9 up to 1e3
+    let text = format!("{:.*e}", precision - 1, x);
+    let (mantissa, exponent) = text.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    if (-5..(precision as i32).min(15)).contains(&exponent) {
+        trim_zeros(significant(x, precision))
+    }
+    else {
+        format!("{}e{}", trim_zeros(mantissa.to_string()), exponent)
+    }
+}
+
+/// Removes trailing zeros after a decimal point, and the point itself if nothing follows it.
+fn trim_zeros(text: String) -> String {
+    if !text.contains('.') {
+        return text;
+    }
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Formats a number with a number of significant digits, without an exponent.
+///
+/// # Arguments
+/// * `x` - The number to format.
+/// * `digits` - The significant digits to keep, trailing zeros included.
+pub fn significant(x: f64, digits: usize) -> String {
+    if !x.is_finite() {
+        return x.to_string();
+    }
+    // scientific formatting does the rounding, including carries like 9.99 to 10.0
+    let text = format!("{:.*e}", digits - 1, x.abs());
+    let (mantissa, exponent) = text.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let mantissa_digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let sign = if x < 0.0 { "-" } else { "" };
+
+    if exponent < 0 {
+        format!("{}0.{}{}", sign, "0".repeat((-exponent - 1) as usize), mantissa_digits)
+    }
+    else if exponent as usize >= digits - 1 {
+        format!("{}{}{}", sign, mantissa_digits, "0".repeat(exponent as usize + 1 - digits))
+    }
+    else {
+        let (whole, fraction) = mantissa_digits.split_at(exponent as usize + 1);
+        format!("{}{}.{}", sign, whole, fraction)
+    }
+}
+
+/// Formats a number in engineering notation, with an exponent that is a multiple of 3.
+///
+/// # Arguments
+/// * `x` - The number to format.
+/// * `digits` - The digits after the decimal point.
+pub fn engineering(x: f64, digits: usize) -> String {
+    if x == 0.0 || !x.is_finite() {
+        return format!("{:.*}e0", digits, x);
+    }
+    let mut exponent = x.abs().log10().floor() as i32;
+    exponent -= exponent.rem_euclid(3);
+    let mut mantissa = format!("{:.*}", digits, x / 10f64.powi(exponent));
+    // rounding can carry the mantissa up to 1000
+    if mantissa.trim_start_matches('-').starts_with("1000") {
+        exponent += 3;
+        mantissa = format!("{:.*}", digits, x / 10f64.powi(exponent));
+    }
+    format!("{}e{}", mantissa, exponent)
+}
+
+/// Formats a number with a pattern such as `"0.00"`, `"#,##0.##"`, `"0.0%"` or `"0.00E+00"`.
+///
+/// In the pattern `0` is a digit that is always shown and `#` a digit shown only when needed,
+/// `,` in the whole part groups thousands, `%` multiplies by 100 and `e` or `E` followed by
+/// zeros starts an exponent. Any other characters before or after the digits are copied as is.
+///
+/// # Arguments
+/// * `x` - The number to format.
+/// * `pattern` - The pattern to format with.
+///
+/// # Panics
+/// If the pattern has no digits.
+pub fn format_pattern(x: f64, pattern: &str) -> String {
+    let is_digit = |c: char| c == '0' || c == '#' || c == ',' || c == '.';
+    let start = pattern.find(is_digit).unwrap_or_else(|| panic!("Expected pattern '{}' to contain '0' or '#' in function: format", pattern));
+    let mut end = pattern[start..].find(|c: char| !is_digit(c)).map_or(pattern.len(), |i| start + i);
+    let number_pattern = &pattern[start..end];
+
+    // exponent, such as "e0" or "E+00"
+    let mut exponent_pattern = "";
+    let rest = &pattern[end..];
+    if rest.starts_with(['e', 'E']) {
+        let length = 1 + rest[1..].chars().take_while(|c| *c == '+' || *c == '0').count();
+        if rest[1..length].contains('0') {
+            exponent_pattern = &rest[..length];
+            end += length;
+        }
+    }
+    let (prefix, suffix) = (&pattern[..start], &pattern[end..]);
+
+    let (whole_pattern, fraction_pattern) = number_pattern.split_once('.').unwrap_or((number_pattern, ""));
+    let grouping = whole_pattern.contains(',');
+    let min_whole = whole_pattern.chars().filter(|c| *c == '0').count();
+    let min_fraction = fraction_pattern.chars().filter(|c| *c == '0').count();
+    let max_fraction = fraction_pattern.chars().filter(|c| *c == '0' || *c == '#').count();
+
+    let mut value = x;
+    if prefix.contains('%') || suffix.contains('%') {
+        value *= 100.0;
+    }
+    if !value.is_finite() {
+        return format!("{}{}{}", prefix, value, suffix);
+    }
+
+    let mut exponent_text = String::new();
+    if !exponent_pattern.is_empty() {
+        let mut exponent = if value == 0.0 { 0 } else { value.abs().log10().floor() as i32 };
+        // keep the whole part at the number of required whole digits, at least one
+        exponent -= min_whole.max(1) as i32 - 1;
+        value /= 10f64.powi(exponent);
+        // rounding can carry the mantissa up a digit
+        if format!("{:.*}", max_fraction, value.abs()).len() > max_fraction + min_whole.max(1) + usize::from(max_fraction > 0) {
+            exponent += 1;
+            value /= 10.0;
+        }
+        let digits = exponent_pattern.chars().filter(|c| *c == '0').count();
+        let sign = if exponent < 0 { "-" } else if exponent_pattern.contains('+') { "+" } else { "" };
+        exponent_text = format!("{}{}{:0width$}", &exponent_pattern[..1], sign, exponent.abs(), width = digits);
+    }
+
+    let rounded = format!("{:.*}", max_fraction, value.abs());
+    let (whole, fraction) = rounded.split_once('.').unwrap_or((&rounded, ""));
+    let mut fraction = fraction.to_string();
+    while fraction.len() > min_fraction && fraction.ends_with('0') {
+        fraction.pop();
+    }
+    let mut whole = whole.trim_start_matches('0').to_string();
+    while whole.len() < min_whole {
+        whole.insert(0, '0');
+    }
+    if grouping {
+        let digits: Vec<char> = whole.chars().collect();
+        whole = String::new();
+        for (i, c) in digits.iter().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                whole.push(',');
+            }
+            whole.push(*c);
+        }
+    }
+
+    let sign = if x < 0.0 && (whole.chars().any(|c| c.is_ascii_digit() && c != '0') || fraction.chars().any(|c| c != '0')) { "-" } else { "" };
+    let point = if fraction.is_empty() { "" } else { "." };
+    let whole = if whole.is_empty() && fraction.is_empty() { "0".to_string() } else { whole };
+    format!("{}{}{}{}{}{}{}", sign, prefix, whole, point, fraction, exponent_text, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::run_script;
+    use crate::limits::Limits;
+
+    #[test]
+    fn patterns_round_group_and_scale() {
+        assert_eq!(format_pattern(1234567.891, "#,##0.00"), "1,234,567.89");
+        assert_eq!(format_pattern(0.5, "#.##"), ".5");
+        assert_eq!(format_pattern(7.0, "000"), "007");
+        assert_eq!(format_pattern(0.25, "0.0%"), "25.0%");
+        assert_eq!(format_pattern(-3.5, "$0.00"), "-$3.50");
+        assert_eq!(format_pattern(12345.0, "0.00E+00"), "1.23E+04");
+        assert_eq!(format_pattern(0.00012, "0.0e0"), "1.2e-4");
+        // rounding carries 9.996 up an exponent
+        assert_eq!(format_pattern(9.996, "0.00e0"), "1.00e1");
+    }
+
+    #[test]
+    fn significant_digits_carry_and_keep_zeros() {
+        assert_eq!(significant(123456.0, 3), "123000");
+        assert_eq!(significant(9.996, 3), "10.0");
+        assert_eq!(significant(-0.00012345, 2), "-0.00012");
+        assert_eq!(significant(1.5, 4), "1.500");
+        assert_eq!(engineering(12345.0, 2), "12.35e3");
+        assert_eq!(engineering(0.000123, 1), "123.0e-6");
+        assert_eq!(engineering(999999.0, 1), "1.0e6");
+        assert_eq!(engineering(0.0, 2), "0.00e0");
+    }
+
+    #[test]
+    fn precision_writes_an_exponent_rather_than_zeros() {
+        assert_eq!(format_number(0.1 + 0.2, 10), "0.3");
+        assert_eq!(format_number(0.1 + 0.2, 0), "0.30000000000000004");
+        assert_eq!(format_number(123456.0, 3), "1.23e5");
+        assert_eq!(format_number(999.9, 3), "1e3");
+        assert_eq!(format_number(12.345, 3), "12.3");
+        assert_eq!(format_number(-0.000012345, 3), "-0.0000123");
+        assert_eq!(format_number(1.5e-7, 3), "1.5e-7");
+        assert_eq!(format_number(f64::INFINITY, 3), "inf");
+    }
+
+    #[test]
+    fn precision_belongs_to_an_engine() {
+        let script = "precision(3)\ndisplayln(123456, \" \", {2/3})";
+        assert_eq!(run_script(script, "", Limits::default()).unwrap(), "1.23e5 {0.667}\n");
+        // a fresh engine displays with the shortest exact form again
+        assert_eq!(run_script("displayln(2/3)", "", Limits::default()).unwrap(), "0.6666666666666666\n");
+        let errors = run_script("precision(1.5)", "", Limits::default()).unwrap_err();
+        assert!(errors[0].message.starts_with("Expected a whole number of digits"), "{}", errors[0].message);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::diagnostic::{add_file, get_file, raise, raise_all, remove_file, Diagnostic};
+use crate::interpreter::{interpret_with, Context, DefinedFunction, Variable};
+use crate::lexer::{get_lexer_lines, Span};
+use crate::parser::{parse_with, Expr};
+use crate::prelude;
+use std::cell::RefCell;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The files one run of a script imports, which `Engine::run` starts for each run so a changed file is read again.
+pub struct Imports<'a> {
+    /// Directories searched for an imported file not found next to the file importing it, from `Engine::add_search_path`.
+    search_paths: &'a [PathBuf],
+    /// Canonical and written paths of the files being imported, the script importing the first one at the bottom
+    /// and the innermost last, to find import cycles.
+    stack: RefCell<Vec<(PathBuf, String)>>,
+    /// Canonical path and file index of every file imported, so importing a file again reuses its spans.
+    loaded: RefCell<Vec<(PathBuf, usize)>>
+}
+
+impl<'a> Imports<'a> {
+    /// Starts the imports of a run, looking for files in `search_paths` after the directory of the importing file.
+    pub fn new(search_paths: &'a [PathBuf]) -> Self {
+        Imports { search_paths, stack: RefCell::new(Vec::new()), loaded: RefCell::new(Vec::new()) }
+    }
+
+    /// Forgets the source of the files imported once the run has finished, keeping those its errors point into.
+    pub fn remove_files(&self, errors: &[Diagnostic]) {
+        for (_, file) in self.loaded.borrow().iter() {
+            if !errors.iter().any(|x| x.span.is_some_and(|x| x.file == *file)) {
+                remove_file(*file);
+            }
+        }
+    }
+}
+
+/// Takes a file off the stack of files being imported once it has run or raised an error.
+struct Importing<'a> {
+    stack: &'a RefCell<Vec<(PathBuf, String)>>,
+    /// The length of the stack before the file was added.
+    below: usize
+}
+
+impl Drop for Importing<'_> {
+    fn drop(&mut self) {
+        self.stack.borrow_mut().truncate(self.below);
+    }
+}
+
+/// Runs an imported file and adds the functions and constants it defines to the importing file.
+///
+/// # Arguments
+/// * `path` - The path written after `import`, relative to the importing file or a search path.
+/// * `namespace` - The name written after `as`, which the imported names are prefixed with, as in `trig.sin`.
+/// * `span` - The span of the import statement.
+/// * `defined_functions` - The functions of the importing file.
+/// * `variables` - The variables and constants of the importing file.
+/// * `context` - The native functions and the state of the run, shared with the imported file.
+pub fn import(path: &str, namespace: Option<&str>, span: Span, defined_functions: &mut Vec<DefinedFunction>, variables: &mut Vec<Variable>, context: &Context) {
+    let imports = context.imports;
+    let (importer, _) = get_file(span.file).unwrap_or_default();
+    let resolved = resolve(path, &importer, imports.search_paths).unwrap_or_else(|searched| {
+        let hint = format!("searched {}", searched.iter().map(|x| format!("'{}'", x.display())).collect::<Vec<_>>().join(", "));
+        raise(Diagnostic::new(format!("Could not find '{}' to import", path)).at(span).with_hint(Some(hint)))
+    });
+    let canonical = resolved.canonicalize().unwrap_or_else(|_| resolved.clone());
+    let written = resolved.display().to_string();
+
+    // declared before borrowing the stack, so an error raised while it is borrowed drops the borrow first
+    let importing = Importing { stack: &imports.stack, below: imports.stack.borrow().len() };
+    let mut stack = imports.stack.borrow_mut();
+    if stack.is_empty() {
+        // the script importing the first file is at the bottom of every chain of imports
+        stack.push((Path::new(&importer).canonicalize().unwrap_or_else(|_| PathBuf::from(&importer)), importer));
+    }
+    if stack.iter().any(|(x, _)| *x == canonical) {
+        let cycle: Vec<&str> = stack.iter().map(|(_, x)| x.as_str()).chain([written.as_str()]).collect();
+        raise(Diagnostic::new(format!("Import cycle: {}", cycle.join(" -> "))).at(span));
+    }
+    stack.push((canonical.clone(), written));
+    drop(stack);
+
+    let file = load(&canonical, &resolved, span, &imports.loaded);
+    let (_, contents) = get_file(file).unwrap_or_default();
+    let (lines, mut diagnostics) = get_lexer_lines(&contents, file);
+    let (lines, parser_diagnostics) = parse_with(lines, context.counter.limits());
+    diagnostics.extend(parser_diagnostics);
+    if !diagnostics.is_empty() {
+        raise_all(diagnostics);
+    }
+    let (defined, variables_defined) = prelude::session(context);
+    let (functions, constants) = interpret_with(lines, defined, variables_defined, context);
+    drop(importing);
+
+    // only what the imported file defines itself, not the prelude it starts with
+    let functions: Vec<DefinedFunction> = functions.into_iter().filter(|x| !x.is_builtin).collect();
+    let constants: Vec<Variable> = constants.into_iter().filter(|x| x.is_constant && !x.is_builtin).collect();
+    let names: Vec<String> = functions.iter().map(|x| x.name.clone()).chain(constants.iter().map(|x| x.name.clone())).collect();
+    let qualified = |name: &str| match namespace {
+        Some(namespace) if names.iter().any(|x| x == name) => format!("{}.{}", namespace, name),
+        _ => name.to_string(),
+    };
+
+    for mut function in functions {
+        function.name = qualified(&function.name);
+        if let Some(body) = &mut function.function {
+            qualify(body, &qualified);
+        }
+        match defined_functions.iter().find(|x| x.name == function.name) {
+            // the same definition, imported again through another file
+            Some(existing) if existing.function.as_ref().map(|x| x.span()) == function.function.as_ref().map(|x| x.span()) => {}
+            Some(existing) if existing.is_builtin => {
+                defined_functions.retain(|x| x.name != function.name);
+                defined_functions.push(function);
+            }
+            Some(_) => {
+                let stem = Path::new(path).file_stem().and_then(|x| x.to_str()).filter(|x| x.chars().all(|c| c.is_ascii_lowercase())).unwrap_or("lib");
+                let hint = format!("import it under a namespace, such as `import \"{}\" as {}`", path, stem);
+                raise(Diagnostic::new(format!("Function '{}' is already defined", function.name)).at(span).with_hint(Some(hint)));
+            }
+            None => defined_functions.push(function),
+        }
+    }
+    for mut constant in constants {
+        constant.name = qualified(&constant.name);
+        match variables.iter().position(|x| x.name == constant.name) {
+            Some(index) if variables[index].is_builtin => variables[index] = constant,
+            Some(index) if variables[index].value == constant.value => {}
+            Some(_) => raise(Diagnostic::new(format!("Constant {} can not be redefined", constant.name)).at(span)),
+            None => variables.push(constant),
+        }
+    }
+}
+
+/// Finds an imported file next to the importing file, then in each search path.
+///
+/// # Returns
+/// The path of the file, or every path tried when it was not found.
+fn resolve(path: &str, importer: &str, search_paths: &[PathBuf]) -> Result<PathBuf, Vec<PathBuf>> {
+    let directory = Path::new(importer).parent().unwrap_or(Path::new("")).to_path_buf();
+    let mut searched = vec![directory];
+    searched.extend(search_paths.iter().cloned());
+    match searched.iter().map(|x| x.join(path)).find(|x| x.is_file()) {
+        Some(found) => Ok(found),
+        None => Err(searched.into_iter().map(|x| if x.as_os_str().is_empty() { PathBuf::from(".") } else { x }).collect()),
+    }
+}
+
+/// Reads an imported file the first time a run imports it.
+///
+/// # Returns
+/// The index of the file, for `Span::file`.
+fn load(canonical: &Path, resolved: &Path, span: Span, loaded: &RefCell<Vec<(PathBuf, usize)>>) -> usize {
+    if let Some((_, file)) = loaded.borrow().iter().find(|(x, _)| x == canonical) {
+        return *file;
+    }
+    let contents = fs::read_to_string(resolved).unwrap_or_else(|e| {
+        raise(Diagnostic::new(format!("Could not read '{}': {}", resolved.display(), e)).at(span))
+    });
+    let file = add_file(&resolved.display().to_string(), &contents);
+    loaded.borrow_mut().push((canonical.to_path_buf(), file));
+    file
+}
+
+/// Renames the calls and constants of an imported function body that refer to the imported file's own definitions.
+fn qualify(node: &mut Expr, qualified: &impl Fn(&str) -> String) {
+    match node {
+        Expr::Variable { name, .. } => *name = qualified(name),
+        Expr::Call { name, arguments, .. } => {
+            *name = qualified(name);
+            arguments.iter_mut().for_each(|x| qualify(x, qualified));
+        }
+        Expr::Binary { left, right, .. } => {
+            qualify(left, qualified);
+            qualify(right, qualified);
+        }
+        Expr::Chain { operands, .. } => operands.iter_mut().for_each(|x| qualify(x, qualified)),
+        Expr::Unary { operand, .. } => qualify(operand, qualified),
+        Expr::Set { elements, .. } => elements.iter_mut().for_each(|x| qualify(x, qualified)),
+        Expr::Index { set, index, .. } => {
+            qualify(set, qualified);
+            qualify(index, qualified);
+        }
+        Expr::Conditional { condition, value, otherwise, .. } => {
+            qualify(condition, qualified);
+            qualify(value, qualified);
+            if let Some(otherwise) = otherwise {
+                qualify(otherwise, qualified);
+            }
+        }
+        Expr::Number { .. } | Expr::Text { .. } | Expr::Assign { .. } | Expr::FuncDef { .. } | Expr::Marker { .. } | Expr::Import { .. } => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Engine;
+    use crate::io::MemoryIo;
+    use crate::parser::parse;
+    use std::rc::Rc;
+
+    /// Writes the files of a test to a directory of its own.
+    fn directory(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("calc_lang_{}_{}", test, std::process::id()));
+        for (path, contents) in files {
+            let path = directory.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        directory
+    }
+
+    /// Runs a file, returning what it displayed or the message of the error that stopped it.
+    fn run(engine: &mut Engine, path: &Path) -> Result<String, String> {
+        let contents = fs::read_to_string(path).unwrap();
+        let (lines, _) = get_lexer_lines(&contents, add_file(&path.display().to_string(), &contents));
+        let io = Rc::new(RefCell::new(MemoryIo::new("")));
+        engine.set_io(io.clone());
+        engine.run(parse(lines).0).map_err(|x| x[0].message.clone())?;
+        let output = io.borrow().output.clone();
+        Ok(output)
+    }
+
+    #[test]
+    fn imports_are_namespaced_and_found_on_search_paths() {
+        let directory = directory("search", &[
+            ("main.calc", "import \"lib.calc\" as lib\nimport \"helper.calc\"\ndisplayln(lib.twice(2))\ndisplayln(helper(1))"),
+            ("lib.calc", "twice(X) => 2X"),
+            ("other/helper.calc", "helper(X) => X + 10")
+        ]);
+        let mut engine = Engine::new();
+        let error = run(&mut engine, &directory.join("main.calc")).unwrap_err();
+        assert_eq!(error, "Could not find 'helper.calc' to import");
+        engine.add_search_path(directory.join("other"));
+        assert_eq!(run(&mut engine, &directory.join("main.calc")), Ok("4\n11\n".to_string()));
+    }
+
+    #[test]
+    fn cycles_start_at_the_importing_script() {
+        let directory = directory("cycle", &[("a.calc", "import \"b.calc\""), ("b.calc", "import \"a.calc\"")]);
+        let error = run(&mut Engine::new(), &directory.join("a.calc")).unwrap_err();
+        let (a, b) = (directory.join("a.calc").display().to_string(), directory.join("b.calc").display().to_string());
+        assert_eq!(error, format!("Import cycle: {} -> {} -> {}", a, b, a));
+    }
+
+    #[test]
+    fn failed_imports_leave_the_next_run_untouched() {
+        let directory = directory("failed", &[("main.calc", "import \"rt.calc\""), ("rt.calc", "g(X) => nope(X)\ng(1)")]);
+        let mut engine = Engine::new();
+        for _ in 0..2 {
+            assert_eq!(run(&mut engine, &directory.join("main.calc")), Err("Function 'nope' not defined".to_string()));
+        }
+    }
+
+    #[test]
+    fn imported_files_are_forgotten_after_a_run() {
+        let directory = directory("forgotten", &[("main.calc", "import \"lib.calc\"\nf(1)"), ("lib.calc", "f(X) => X")]);
+        let contents = fs::read_to_string(directory.join("main.calc")).unwrap();
+        let script = add_file(&directory.join("main.calc").display().to_string(), &contents);
+        let (lines, _) = get_lexer_lines(&contents, script);
+        Engine::new().run(parse(lines).0).unwrap();
+        // the script stays for the host to remove, files of other tests being added meanwhile
+        let lib = directory.join("lib.calc").display().to_string();
+        assert!((script + 1..script + 1000).filter_map(get_file).all(|(path, _)| path != lib));
+        assert!(get_file(script).is_some());
+
+        // unless an error points into it
+        fs::write(directory.join("lib.calc"), "f(X) => nope(X)").unwrap();
+        let errors = Engine::new().run(parse(get_lexer_lines(&contents, script).0).0).unwrap_err();
+        let (path, _) = get_file(errors[0].span.unwrap().file).unwrap();
+        assert_eq!(path, lib);
+    }
+
+    #[test]
+    fn changed_files_are_read_again() {
+        let directory = directory("changed", &[("main.calc", "import \"lib.calc\"\ndisplayln(k)"), ("lib.calc", "1 -> k")]);
+        let mut engine = Engine::new();
+        assert_eq!(run(&mut engine, &directory.join("main.calc")), Ok("1\n".to_string()));
+        fs::write(directory.join("lib.calc"), "2 -> k").unwrap();
+        assert_eq!(run(&mut engine, &directory.join("main.calc")), Ok("2\n".to_string()));
+    }
+}
@@ -1,473 +1,569 @@
-use crate::lexer::TokenType;
-use crate::parser::ExprNode;
-use std::io::stdin;
-use rand::Rng;
+use crate::diagnostic::{raise, suggest, within, Diagnostic};
+use crate::lexer::Span;
+use crate::parser::{is_comparison, BinaryOp, Expr, UnaryOp};
+use crate::numeric::{Callback, NumericFunction, NumericFunctions};
+use crate::format::{format_number, get_format_functions};
+use crate::stats::get_statistics_functions;
+use crate::import::{import, Imports};
+use crate::engine::NativeFn;
+use crate::limits::Counter;
+use crate::prelude::Prelude;
+use std::cell::Cell;
+use std::cmp::Ordering as CmpOrdering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
 
+/// A value produced by evaluating an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(f64),
+    Set(Vec<f64>),
+    Text(String)
+}
+
+/// A built-in taking `parameters` values (-1 for any number).
 pub struct PreDefinedFunction {
     pub name: String,
     pub parameters: i32,
-    pub execute: fn(Vec<(f64, Option<Vec<f64>>)>) -> f64
+    pub execute: fn(Vec<Value>) -> Value
+}
+/// A function implemented in Rust, either a built-in or one registered with `Engine::register_fn`,
+/// taking `parameters` values (-1 for any number).
+pub struct NativeFunction {
+    pub name: String,
+    pub parameters: i32,
+    pub execute: NativeFn
+}
+/// The native functions a session can call, by name.
+pub type NativeFunctions = HashMap<String, NativeFunction>;
+/// What the lines of a run can call, and the state of the run, which `Engine::run` starts for each script.
+pub struct Context<'a> {
+    pub functions: &'a NativeFunctions,
+    pub numeric_functions: &'a NumericFunctions,
+    /// The names of the native functions registered by the host, called in place of the prelude's.
+    pub registered: &'a HashSet<String>,
+    /// The marker `goto` was called with, jumped to once the statement calling it finishes.
+    pub jump: &'a Cell<Option<i32>>,
+    pub imports: &'a Imports<'a>,
+    /// Whether the run and the files it imports start with the prelude, and what it defines.
+    pub prelude: &'a Prelude,
+    /// The error estimate of the last numeric built-in that made one, which `error()` returns.
+    pub last_error: &'a Cell<f64>,
+    pub counter: Counter
 }
 #[derive(Debug, Clone)]
 pub struct DefinedFunction {
     pub name: String,
     pub parameters: Option<Vec<String>>,
-    pub function: Option<Box<ExprNode>>
+    pub function: Option<Box<Expr>>,
+    /// The doc comment written before the definition.
+    pub doc: Option<String>,
+    /// Defined by the prelude, so a script may define a function with the same name in its place.
+    pub is_builtin: bool
 }
 #[derive(Debug, Clone)]
 pub struct Variable {
     pub name: String,
-    pub value: (f64, Option<Vec<f64>>),
-    pub is_constant: bool
+    pub value: Value,
+    pub is_constant: bool,
+    /// A constant defined by the prelude, which a script may redefine once.
+    pub is_builtin: bool
 }
-#[derive(Debug, Clone)]
-pub struct Marker {
-    pub line_num: i32,
-    pub mark: i32
+
+impl Value {
+    /// Returns the number held by the value, panicking if it is a set or text.
+    pub fn number(&self) -> f64 {
+        match self {
+            Value::Number(x) => *x,
+            other => panic!("Expected a number, got {}", other.kind()),
+        }
+    }
+    /// Returns the elements of a set, or `None` for numbers and text.
+    pub fn as_set(&self) -> Option<&Vec<f64>> {
+        match self {
+            Value::Set(set) => Some(set),
+            _ => None,
+        }
+    }
+    /// Returns the name of the kind of value, for error messages.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "a number",
+            Value::Set(_) => "a set",
+            Value::Text(_) => "text",
+        }
+    }
+}
+
+impl Value {
+    /// Formats the value for display, with numbers rounded to `precision` significant digits, 0 keeping as many as
+    /// needed to round-trip.
+    pub fn display(&self, precision: usize) -> String {
+        match self {
+            Value::Number(x) => format_number(*x, precision),
+            Value::Set(set) => {
+                let items: Vec<String> = set.iter().map(|x| format_number(*x, precision)).collect();
+                format!("{{{}}}", items.join(", "))
+            }
+            Value::Text(text) => text.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.display(0))
+    }
 }
 
 impl DefinedFunction {
-    pub fn new(name: String, parameters: Option<Vec<String>>, function: Option<Box<ExprNode>>) -> Self {
+    pub fn new(name: String, parameters: Option<Vec<String>>, function: Option<Box<Expr>>, doc: Option<String>) -> Self {
         DefinedFunction {
             name,
             parameters,
-            function
+            function,
+            doc,
+            is_builtin: false
+        }
+    }
+    /// Describes the function: its signature, followed by its doc comment indented.
+    pub fn help(&self) -> String {
+        let mut help = format!("{}({})", self.name, self.parameters.as_deref().unwrap_or_default().join(", "));
+        match &self.doc {
+            Some(doc) => doc.lines().for_each(|x| help += &format!("\n    {}", x)),
+            None => help += "\n    No documentation.",
+        }
+        help
+    }
+}
+
+impl From<PreDefinedFunction> for NativeFunction {
+    fn from(function: PreDefinedFunction) -> Self {
+        let execute = function.execute;
+        NativeFunction {
+            name: function.name,
+            parameters: function.parameters,
+            execute: Box::new(move |values| Ok(execute(values.to_vec())))
         }
     }
 }
 
 impl Variable {
-    pub fn new(name: String, value: (f64, Option<Vec<f64>>), is_constant: bool) -> Self {
+    pub fn new(name: String, value: Value, is_constant: bool) -> Self {
         Variable {
             name,
             value,
-            is_constant
+            is_constant,
+            is_builtin: false
         }
     }
 }
 
-/// Returns a vector of predefined functions
+/// Returns a vector of predefined functions, apart from those using the session's I/O in `io::get_io_functions`
+/// and `goto` in `get_goto_function`
 pub fn get_pre_defined_functions() -> Vec<PreDefinedFunction> {
-    vec![
-        PreDefinedFunction {
-            name: "displayln".to_string(),
-            parameters: 1,
-            execute: |v| {
-                println!("{}", v[0].0);
-                0.0
-            }
-        },
+    let mut functions = vec![
         PreDefinedFunction {
-            name: "display".to_string(),
+            name: "chr".to_string(),
             parameters: 1,
             execute: |v| {
-                print!("{}", v[0].0);
-                0.0
+                Value::Text(character(v[0].number(), "chr").to_string())
             }
         },
         PreDefinedFunction {
-            name: "dacln".to_string(),
+            name: "ord".to_string(),
             parameters: 1,
             execute: |v| {
-                unsafe {
-                    println!("{}", char::from_u32_unchecked(v[0].0 as u32));
-                    0.0
+                match &v[0] {
+                    Value::Text(text) if !text.is_empty() => Value::Number(text.chars().next().unwrap() as u32 as f64),
+                    other => panic!("Expected non-empty text, got {} in function: ord", other.kind()),
                 }
             }
         },
         PreDefinedFunction {
-            name: "dac".to_string(),
+            name: "text".to_string(),
             parameters: 1,
             execute: |v| {
-                unsafe {
-                    print!("{}", char::from_u32_unchecked(v[0].0 as u32));
-                    0.0
-                }
-            }
-        },
-        PreDefinedFunction {
-            name: "read".to_string(),
-            parameters: 0,
-            execute: |_| {
-                let mut buffer = String::new();
-            
-                stdin().read_line(&mut buffer).unwrap_or(0);
-                match buffer.trim_end() {
-                    "" => return FALSE,
-                    out => return out.parse::<f64>().unwrap_or(0.0),
-                };
-            }
-        },
-        PreDefinedFunction {
-            name: "clear".to_string(),
-            parameters: 0,
-            execute: |_| {
-                println!("\x1B[2J\x1B[1;1H");
-                0.0
+                Value::Text(v[0].to_string())
             }
         },
         PreDefinedFunction {
             name: "len".to_string(),
             parameters: 1,
             execute: |x| {
-                x[0].1.as_ref().unwrap().len() as f64
-            }
-        },
-        PreDefinedFunction {
-            name: "rand".to_string(),
-            parameters: 0,
-            execute: |x| {
-                rand::random::<f64>() 
+                match &x[0] {
+                    Value::Set(set) => Value::Number(set.len() as f64),
+                    Value::Text(text) => Value::Number(text.chars().count() as f64),
+                    other => panic!("Expected a set or text, got {} in function: len", other.kind()),
+                }
             }
         },
         PreDefinedFunction {
-            name: "goto".to_string(),
+            name: "sqrt".to_string(),
             parameters: 1,
-            execute: |v| {
-                unsafe {
-                    let mark = v[0].0 as i32;
-                    let line = MARKERS.iter().find(|x| x.mark == mark).unwrap();
-                    
-                    NEXT_MARKER = line.clone();
-                    SET_LINE = true;
-
-                    line.line_num as f64
-                }
-            }
+            execute: |v| Value::Number(v[0].number().sqrt())
         }
-    ]
+    ];
+    functions.extend(get_statistics_functions());
+    functions.extend(get_format_functions());
+    functions
+}
+
+/// Returns `goto`, which sets the marker the lines running jump to.
+pub fn get_goto_function(jump: &Rc<Cell<Option<i32>>>) -> NativeFunction {
+    let jump = jump.clone();
+    NativeFunction {
+        name: "goto".to_string(),
+        parameters: 1,
+        execute: Box::new(move |v| {
+            let mark = v[0].number();
+            jump.set(Some(mark as i32));
+            Ok(Value::Number(mark))
+        })
+    }
+}
+
+/// Joins the displayed form of every value, for the display functions.
+pub(crate) fn concatenate(values: &[Value], precision: usize) -> String {
+    values.iter().map(|x| x.display(precision)).collect()
+}
+
+/// Converts a character code to a character.
+/// 
+/// # Arguments
+/// * `code` - The Unicode code point.
+/// * `name` - The name of the calling function, for error messages.
+/// 
+/// # Panics
+/// If `code` is not a valid Unicode code point.
+pub(crate) fn character(code: f64, name: &str) -> char {
+    if code < 0.0 || code.fract() != 0.0 || code > u32::MAX as f64 {
+        panic!("Expected a character code, got {} in function: {}", code, name);
+    }
+    char::from_u32(code as u32).unwrap_or_else(|| panic!("Expected a character code, got {} in function: {}", code, name))
 }
 /// Returns a vector of Variable structs initialized with default values from 'A' to 'Z'.
 pub fn get_variables() -> Vec<Variable> {
     [
-        Variable::new("A".to_string(), (FALSE, None), false),
-        Variable::new("B".to_string(), (FALSE, None), false),
-        Variable::new("C".to_string(), (FALSE, None), false),
-        Variable::new("D".to_string(), (FALSE, None), false),
-        Variable::new("E".to_string(), (FALSE, None), false),
-        Variable::new("F".to_string(), (FALSE, None), false),
-        Variable::new("G".to_string(), (FALSE, None), false),
-        Variable::new("H".to_string(), (FALSE, None), false),
-        Variable::new("I".to_string(), (FALSE, None), false),
-        Variable::new("J".to_string(), (FALSE, None), false),
-        Variable::new("K".to_string(), (FALSE, None), false),
-        Variable::new("L".to_string(), (FALSE, None), false),
-        Variable::new("M".to_string(), (FALSE, None), false),
-        Variable::new("N".to_string(), (FALSE, None), false),
-        Variable::new("O".to_string(), (FALSE, None), false),
-        Variable::new("P".to_string(), (FALSE, None), false),
-        Variable::new("Q".to_string(), (FALSE, None), false),
-        Variable::new("R".to_string(), (FALSE, None), false),
-        Variable::new("S".to_string(), (FALSE, None), false),
-        Variable::new("T".to_string(), (FALSE, None), false),
-        Variable::new("U".to_string(), (FALSE, None), false),
-        Variable::new("V".to_string(), (FALSE, None), false),
-        Variable::new("W".to_string(), (FALSE, None), false),
-        Variable::new("X".to_string(), (FALSE, None), false),
-        Variable::new("Y".to_string(), (FALSE, None), false),
-        Variable::new("Z".to_string(), (FALSE, None), false),
+        Variable::new("A".to_string(), Value::Number(FALSE), false),
+        Variable::new("B".to_string(), Value::Number(FALSE), false),
+        Variable::new("C".to_string(), Value::Number(FALSE), false),
+        Variable::new("D".to_string(), Value::Number(FALSE), false),
+        Variable::new("E".to_string(), Value::Number(FALSE), false),
+        Variable::new("F".to_string(), Value::Number(FALSE), false),
+        Variable::new("G".to_string(), Value::Number(FALSE), false),
+        Variable::new("H".to_string(), Value::Number(FALSE), false),
+        Variable::new("I".to_string(), Value::Number(FALSE), false),
+        Variable::new("J".to_string(), Value::Number(FALSE), false),
+        Variable::new("K".to_string(), Value::Number(FALSE), false),
+        Variable::new("L".to_string(), Value::Number(FALSE), false),
+        Variable::new("M".to_string(), Value::Number(FALSE), false),
+        Variable::new("N".to_string(), Value::Number(FALSE), false),
+        Variable::new("O".to_string(), Value::Number(FALSE), false),
+        Variable::new("P".to_string(), Value::Number(FALSE), false),
+        Variable::new("Q".to_string(), Value::Number(FALSE), false),
+        Variable::new("R".to_string(), Value::Number(FALSE), false),
+        Variable::new("S".to_string(), Value::Number(FALSE), false),
+        Variable::new("T".to_string(), Value::Number(FALSE), false),
+        Variable::new("U".to_string(), Value::Number(FALSE), false),
+        Variable::new("V".to_string(), Value::Number(FALSE), false),
+        Variable::new("W".to_string(), Value::Number(FALSE), false),
+        Variable::new("X".to_string(), Value::Number(FALSE), false),
+        Variable::new("Y".to_string(), Value::Number(FALSE), false),
+        Variable::new("Z".to_string(), Value::Number(FALSE), false),
     ].to_vec()
 }
-pub(crate) static mut MARKERS: Vec<Marker> = Vec::new();
-static mut NEXT_MARKER: Marker = Marker { mark: 0, line_num: 0 };
-static mut SET_LINE: bool = false;
-const COLON_DEFAULT_FALSE_VALUE: f64 = -995413546.537209999;
 const TRUE: f64 = 1.0;
 const FALSE: f64 = 0.0;
 
-/// # Interpret
-/// Interprets the given lines of expressions using the default variables and an empty list of defined functions.
-/// Calls the internal function 'interpret_with' with the provided lines, an empty list of defined functions, and default variables.
-pub fn interpret(lines: Vec<Option<ExprNode>>) {
-    interpret_with(lines, vec![], get_variables());
-}
-
 /// Interprets a series of lines of code, handling function definitions, variable assignments, and markers.
 /// 
 /// # Arguments
-/// - `all_lines`: A vector of optional expression nodes representing the lines of code to interpret.
+/// - `all_lines`: A vector of optional expressions representing the lines of code to interpret.
 /// - `defined_functions`: A mutable vector of defined functions.
 /// - `variables`: A mutable vector of variables.
+/// - `context`: The native functions and the state of the run.
 /// 
-/// # Safety
-/// This function uses unsafe code to handle markers.
+/// # Returns
+/// The functions and variables defined once every line has run, which an importing file takes its definitions from.
 /// 
 /// # Panics
-/// - If an expected operand is missing or if a variable is not defined during assignment.
+/// - If a constant is redefined or a variable is not defined during assignment.
 /// 
 /// # Notes
-/// The function recursively interprets lines and can skip lines based on markers.
-pub fn interpret_with(all_lines: Vec<Option<ExprNode>>, mut defined_functions: Vec<DefinedFunction>, mut variables: Vec<Variable>) {
-    let pre_defined_functions: Vec<PreDefinedFunction> = get_pre_defined_functions();
-    let lines_binding = all_lines.clone();
-    let lines= lines_binding.iter().cloned();
-    
-    for (_, line) in lines.enumerate() {
-        if line.is_none() {
-            continue;
+/// After `goto` the lines run again from the statement after its marker, which must be one of these lines.
+pub fn interpret_with(all_lines: Vec<Option<Expr>>, mut defined_functions: Vec<DefinedFunction>, mut variables: Vec<Variable>, context: &Context) -> (Vec<DefinedFunction>, Vec<Variable>) {
+    let statements: Vec<&Expr> = all_lines.iter().flatten().collect();
+    // the statement of each marker, the first one written for a mark
+    let mut markers: HashMap<i32, usize> = HashMap::new();
+    for (index, line) in statements.iter().enumerate() {
+        if let Expr::Marker { mark, .. } = line {
+            markers.entry(*mark).or_insert(index);
         }
-        if let Some(line) = line {
-            let token = line.token.clone();
-            match token.token_type {
-                TokenType::DoubleArrow => {
-                    if line.operand1.is_some() && line.operand2.is_some() {
-                        // defining a function
-                        let name = line.operand1.clone().unwrap().func_name.unwrap();
-                        let parameters_full = line.operand1.clone().unwrap().func_parameters.unwrap();
-                        let paremeters: Vec<String> = parameters_full.iter().map(|x| x[0].clone().unwrap().c).collect();
-                        let function = line.operand2.clone().unwrap();
-
-                        defined_functions.push(DefinedFunction::new(name, Some(paremeters), Some(function)));
-                    }
-                    else {
-                        panic!("Expected operand1 and operand2 with operator {} in line {}", token.token_type.to_string(), line.line);
-                    }
-                }
-                TokenType::Arrow => {
-                    if line.operand1.is_some() && line.operand2.is_some() {
-                        // assigning a variable
-                        let value = solve_node(line.operand1.as_ref().unwrap(), &variables, &defined_functions, &pre_defined_functions);
-                        let name = line.operand2.clone().unwrap().c;
+    }
+    let mut index = 0;
+    while let Some(&line) = statements.get(index) {
+        index += 1;
+        match line {
+            Expr::FuncDef { name, parameters, body, doc, .. } => {
+                // defining a function, in place of the prelude's
+                defined_functions.retain(|x| !(x.is_builtin && &x.name == name));
+                defined_functions.push(DefinedFunction::new(name.clone(), Some(parameters.clone()), Some(body.clone()), doc.clone()));
+            }
+            Expr::Assign { value, name, target, .. } => {
+                // assigning a variable
+                let value = solve_node(value, &variables, &defined_functions, context);
 
-                        if line.operand2.clone().unwrap().token.token_type == TokenType::Lowercase {
-                            // defining constant
-                            if variables.iter().any(|x| x.name == name) {
-                                panic!("Constant {} can not be redefined in line {}", name, line.line);
-                            }
-                            variables.push(Variable::new(name, value, true));
-                        }
-                        else if let Some(index) = variables.iter().position(|x| x.name == name) {
-                            // assigning variable
-                            variables[index].value = value;
-                        }
-                        else {
-                            panic!("Variable {} can not be defined in line {}", name, line.line);
-                        }
-                    }
-                    else {
-                        panic!("Expected operand1 and operand2 with operator {} in line {}", token.token_type.to_string(), line.line);
+                if name.chars().all(|c| c.is_lowercase()) {
+                    // defining constant
+                    match variables.iter().position(|x| &x.name == name) {
+                        Some(index) if variables[index].is_builtin => variables[index] = Variable::new(name.clone(), value, true),
+                        Some(_) => raise(Diagnostic::new(format!("Constant {} can not be redefined", name)).at(*target)),
+                        None => variables.push(Variable::new(name.clone(), value, true)),
                     }
                 }
-                TokenType::DollarSign => {
-                    /*let line_num = line.marker_line.unwrap();
-                    let mark = line.marker_num.unwrap();
-                    unsafe {
-                        MARKERS.push(Marker {line_num, mark});
-                    }*/
-                    // assigned in parsing
+                else if let Some(index) = variables.iter().position(|x| &x.name == name) {
+                    // assigning variable
+                    variables[index].value = value;
                 }
-                _ => {
-                    solve_node(&line, &variables, &defined_functions, &pre_defined_functions);
+                else {
+                    raise(Diagnostic::new(format!("Variable {} can not be defined", name)).at(*target));
                 }
             }
-        }
-        unsafe { 
-            if SET_LINE {
-                SET_LINE = false;
-                let mut new_lines: Vec<Option<ExprNode>> = vec![];
-
-                for (_, l) in all_lines.iter().enumerate() {
-                    if let Some(l) = l {
-                        if l.line >= NEXT_MARKER.line_num {
-                            new_lines.push(Some(l.clone()));
-                        }
-                    }
-                }
-                
-                interpret_with(new_lines, defined_functions, variables);
-                break;
+            Expr::Marker { .. } => {
+                // found before running
+            }
+            Expr::Import { path, namespace, span } => {
+                import(path, namespace.as_deref(), *span, &mut defined_functions, &mut variables, context);
+            }
+            _ => {
+                solve_node(line, &variables, &defined_functions, context);
             }
         }
+        if let Some(mark) = context.jump.take() {
+            let Some(marker) = markers.get(&mark) else {
+                raise(Diagnostic::new(format!("Marker ${} does not exist in function: goto", mark)).at(line.span()));
+            };
+            // running again from the statement after the marker
+            index = marker + 1;
+        }
     }
+    (defined_functions, variables)
 }
 
-/// Solves an expression node recursively based on its token type and operands.
+/// Solves an expression recursively based on its kind and operands, counting it towards the limits of the script.
 /// 
 /// # Arguments
-/// - `node`: An expression node to be evaluated.
+/// - `node`: An expression to be evaluated.
 /// - `variables`: A vector of variables used in the expression.
 /// - `defined_functions`: A vector of user-defined functions.
-/// - `pre_defined_functions`: A vector of pre-defined functions.
+/// - `context`: The native functions and the state of the run.
 /// 
 /// # Returns
-/// The result of the expression evaluation as a floating-point number.
-pub fn solve_node(node: &ExprNode, variables: &Vec<Variable>, defined_functions: &Vec<DefinedFunction>, pre_defined_functions: &Vec<PreDefinedFunction>) -> (f64, Option<Vec<f64>>) {
-    let mut left =  (FALSE, None);
-    let mut right = (FALSE, None);
-    if node.token.token_type.is_operator() {
-        left = solve_node(node.operand1.as_ref().unwrap(), variables, defined_functions, pre_defined_functions);
-        right = solve_node(node.operand2.as_ref().unwrap(), variables, defined_functions, pre_defined_functions);
-    }
-    match node.token.token_type {
-        TokenType::Number => (node.token.value.parse().unwrap_or(0.0), None),
-        TokenType::Plus => { 
-            if left.1.is_some() || right.1.is_some() {
-                panic!("Expected operand1 and operand2 to not be set with operator {} in line {}", node.token.token_type.to_string(), node.line);
-            }
-            (left.0 + right.0, None) 
-        }
-        TokenType::Dash => {
-            if left.1.is_some() || right.1.is_some() {
-                panic!("Expected operand1 and operand2 to not be set with operator {} in line {}", node.token.token_type.to_string(), node.line);
-            } 
-            (left.0 - right.0, None) 
-        }
-        TokenType::Star => {
-            if left.1.is_some() || right.1.is_some() {
-                panic!("Expected operand1 and operand2 to not be set with operator {} in line {}", node.token.token_type.to_string(), node.line);
-            } 
-            (left.0 * right.0, None) 
-        }
-        TokenType::Slash => {
-            if left.1.is_some() || right.1.is_some() {
-                panic!("Expected operand1 and operand2 to not be set with operator {} in line {}", node.token.token_type.to_string(), node.line);
-            } 
-            (left.0 / right.0, None) 
-        }
-        TokenType::Carrot => {
-            if left.1.is_some() || right.1.is_some() {
-                panic!("Expected operand1 and operand2 to not be set with operator {} in line {}", node.token.token_type.to_string(), node.line);
-            } 
-            (left.0.powf(right.0), None) 
-        }
-        TokenType::Percantage => {
-            if left.1.is_some() || right.1.is_some() {
-                panic!("Expected operand1 and operand2 to not be set with operator {} in line {}", node.token.token_type.to_string(), node.line);
-            }
-            (left.0 % right.0, None)
-        }
-        TokenType::Exclamation => {
-            if left.1.is_some() || right.1.is_some() {
-                panic!("Expected operand1 and operand2 to not be set with operator {} in line {}", node.token.token_type.to_string(), node.line);
-            }
-            (factorial(solve_node(node.operand1.as_ref().unwrap(), variables, defined_functions, pre_defined_functions).0), None)
-        }
-        TokenType::Equal => {
-            if left.1.is_some() || right.1.is_some() {
-                (bool_as_f64(left.1.unwrap().iter().zip(right.1.unwrap().iter()).all(|(x, y)| x == y)), None)
-            }
-            else {
-                if left == right { (TRUE, None) }
-                else { (FALSE, None) }
-            }
-        }
-        TokenType::GreaterThan => {
-            if left.1.is_some() || right.1.is_some() {
-                (bool_as_f64(left.1.unwrap().iter().zip(right.1.unwrap().iter()).all(|(x, y)| x > y)), None)
-            }
-            else {
-                if left > right { (TRUE, None) }
-                else { (FALSE, None) }
+/// The value of the expression.
+pub fn solve_node(node: &Expr, variables: &Vec<Variable>, defined_functions: &Vec<DefinedFunction>, context: &Context) -> Value {
+    context.counter.step(node.span());
+    let value = evaluate(node, variables, defined_functions, context);
+    context.counter.check_set(&value, node.span());
+    value
+}
+
+/// Solves an expression based on its kind, solving its operands with `solve_node`.
+fn evaluate(node: &Expr, variables: &Vec<Variable>, defined_functions: &Vec<DefinedFunction>, context: &Context) -> Value {
+    let solve = |node: &Expr| solve_node(node, variables, defined_functions, context);
+    match node {
+        Expr::Number { value, .. } => Value::Number(*value),
+        Expr::Text { value, .. } => Value::Text(value.clone()),
+        Expr::Variable { name, span } => {
+            for var in variables {
+                if &var.name == name {
+                    return var.value.clone();
+                }
             }
+            let hint = suggest(name, variables.iter().map(|x| x.name.as_str()));
+            raise(Diagnostic::new(format!("Variable '{}' does not exist", name)).at(*span).with_hint(hint));
         }
-        TokenType::GreaterThanOrEqualTo => {
-            if left.1.is_some() || right.1.is_some() {
-                (bool_as_f64(left.1.unwrap().iter().zip(right.1.unwrap().iter()).all(|(x, y)| x >= y)), None)
-            }
-            else {
-                if left >= right { (TRUE, None) }
-                else { (FALSE, None) }
-            }
+        Expr::Binary { op: op @ (BinaryOp::And | BinaryOp::Or | BinaryOp::Xor), left, right, .. } => {
+            let what = format!("the operands of {}", op.to_string().trim());
+            let left_truth = truth(solve(left), left.span(), &what);
+            // and, or only evaluate the right operand when it decides the result
+            if *op != BinaryOp::Xor && left_truth == (*op == BinaryOp::Or) {
+                return Value::Number(bool_as_f64(left_truth));
+            }
+            let right_truth = truth(solve(right), right.span(), &what);
+            Value::Number(bool_as_f64(if *op == BinaryOp::Xor { left_truth != right_truth } else { right_truth }))
         }
-        TokenType::LessThan => {
-            if left.1.is_some() || right.1.is_some() {
-                (bool_as_f64(left.1.unwrap().iter().zip(right.1.unwrap().iter()).all(|(x, y)| x < y)), None)
-            }
-            else {
-                if left < right { (TRUE, None) }
-                else { (FALSE, None) }
+        Expr::Binary { op, left, right, span } if is_comparison(*op) => compare(*op, *span, &solve(left), &solve(right)),
+        Expr::Binary { op, left, right, span } => {
+            let left = solve(left);
+            let right = solve(right);
+            match op {
+                BinaryOp::Add if matches!(left, Value::Text(_)) || matches!(right, Value::Text(_)) => {
+                    // concatenating text
+                    Value::Text(format!("{}{}", left, right))
+                }
+                BinaryOp::Add => apply_numeric(*op, *span, &left, &right, |x, y| x + y),
+                BinaryOp::Subtract => apply_numeric(*op, *span, &left, &right, |x, y| x - y),
+                BinaryOp::Multiply => apply_numeric(*op, *span, &left, &right, |x, y| x * y),
+                BinaryOp::Divide => apply_numeric(*op, *span, &left, &right, |x, y| x / y),
+                BinaryOp::Remainder => apply_numeric(*op, *span, &left, &right, |x, y| x % y),
+                BinaryOp::Power => apply_numeric(*op, *span, &left, &right, f64::powf),
+                _ => unreachable!("comparisons and logical operators are solved above"),
             }
         }
-        TokenType::LessThanOrEqualTo => {
-            if left.1.is_some() || right.1.is_some() {
-                (bool_as_f64(left.1.unwrap().iter().zip(right.1.unwrap().iter()).all(|(x, y)| x <= y)), None)
-            }
-            else {
-                if left <= right { (TRUE, None) }
-                else { (FALSE, None) }
+        Expr::Chain { operands, ops, span } => {
+            let mut left = solve(&operands[0]);
+            for (op, operand) in ops.iter().zip(&operands[1..]) {
+                let right = solve(operand);
+                if compare(*op, *span, &left, &right).number() == FALSE {
+                    return Value::Number(FALSE);
+                }
+                left = right;
             }
+            Value::Number(TRUE)
         }
-        TokenType::Uppercase => {
-            let var_name = &node.token.value;
-            for var in variables {
-                if &var.name == var_name {
-                    return var.value.clone();
+        Expr::Unary { op, operand, span } => {
+            let value = solve(operand);
+            let Value::Number(x) = value else {
+                raise(Diagnostic::new(format!("Expected a number with operator {}, got {}", op.to_string().trim(), value.kind())).at(*span));
+            };
+            match op {
+                UnaryOp::Negate => Value::Number(-x),
+                UnaryOp::Plus => Value::Number(x),
+                UnaryOp::Not => Value::Number(bool_as_f64(!truth(value, operand.span(), "the operand of not"))),
+                UnaryOp::Factorial if x < 0.0 || x.fract() != 0.0 => {
+                    raise(Diagnostic::new(format!("Expected a whole number of at least 0 with operator !, got {}", x)).at(*span));
                 }
+                UnaryOp::Factorial => Value::Number(factorial(x)),
             }
-            panic!("Variable '{}' does not exist in line {}", var_name, node.line);
         }
-        TokenType::Lowercase => {
-            if !node.is_func() {
-                for var in variables {
-                    if &var.name == &node.token.value {
-                        return var.value.clone();
-                    }
-                }
-                panic!("Variable '{}' does not exist in line {}", &node.token.value, node.line);
+        Expr::Call { name, arguments, span } => {
+            // a function the script defines, then one the host registers, then the prelude's and the built-ins
+            let defined = defined_functions.iter().find(|x| &x.name == name);
+            if let Some(func) = defined.filter(|x| !x.is_builtin || !context.registered.contains(name)) {
+                within(*span, || execute_defined_function(func, arguments, variables, defined_functions, context))
             }
-            let func_name = node.func_name.clone().unwrap();
-            if defined_functions.iter().any(|x| x.name == func_name) {
-                let func = defined_functions.iter().find(|x| x.name == func_name).unwrap();
-                let parameters = node.func_parameters.clone().unwrap();
-                execute_defined_function(func, parameters, &variables, &defined_functions, &pre_defined_functions)
+            else if let Some(func) = context.functions.get(name) {
+                within(*span, || execute_pre_defined_function(func, arguments, variables, defined_functions, context))
             }
-            else if pre_defined_functions.iter().any(|x| x.name == func_name) {
-                let func = pre_defined_functions.iter().find(|x| x.name == func_name).unwrap();
-                let parameters = node.func_parameters.clone().unwrap();
-                (execute_pre_defined_function(func, parameters, &variables, &defined_functions, &pre_defined_functions), None)
+            else if let Some(func) = context.numeric_functions.get(name) {
+                within(*span, || execute_numeric_function(func, arguments, variables, defined_functions, context))
             }
             else {
-                panic!("Function '{}' not defined in line {}", func_name, node.line);
+                let names = defined_functions.iter().map(|x| x.name.as_str())
+                    .chain(context.functions.keys().map(|x| x.as_str()))
+                    .chain(context.numeric_functions.keys().map(|x| x.as_str()));
+                let name_span = Span { length: name.len(), ..*span };
+                raise(Diagnostic::new(format!("Function '{}' not defined", name)).at(name_span).with_hint(suggest(name, names)));
             }
         }
-        TokenType::Semicolon => {
-            left = solve_node(node.operand1.as_ref().unwrap(), variables, defined_functions, pre_defined_functions);
-            right = solve_node(node.operand2.as_ref().unwrap(), variables, defined_functions, pre_defined_functions);
-            if left.0 == COLON_DEFAULT_FALSE_VALUE {
-                return (right.0, None);
+        Expr::Conditional { condition, value, otherwise, .. } => {
+            if truth(solve(condition), condition.span(), "the condition") {
+                solve(value)
             }
             else {
-                (left.0, None)
+                otherwise.as_ref().map_or(Value::Number(FALSE), |x| solve(x))
             }
         }
-        TokenType::Colon => {
-            left = solve_node(node.operand1.as_ref().unwrap(), variables, defined_functions, pre_defined_functions);
-            if left.0 == TRUE {
-                right = solve_node(node.operand2.as_ref().unwrap(), variables, defined_functions, pre_defined_functions);
-                return (right.0, None);
-            }
-            else if left.0 == FALSE {
-                return (COLON_DEFAULT_FALSE_VALUE, None);
-            }
-            panic!("Expected operand1 to have value 0 or 1 in line {}", node.line);
-        }
-        TokenType::OpenCurley => {
-            if node.set.is_some() {
-                let set = node.set.clone().unwrap();
-                let mut result: Vec<f64> = vec![];
-                for i in 0..set.len() {
-                    result.push(solve_node(&set[i][0].as_ref().unwrap(), variables, defined_functions, pre_defined_functions).0);
+        Expr::Set { elements, .. } => {
+            let mut result: Vec<f64> = vec![];
+            for element in elements {
+                match solve(element) {
+                    Value::Number(x) => result.push(x),
+                    other => raise(Diagnostic::new(format!("Expected set elements to be numbers, got {}", other.kind())).at(element.span())),
                 }
-                return (0.0, Some(result));
-            }
-            else {
-                panic!("Expected '{{' to be a set in line {}", node.line);
             }
+            Value::Set(result)
         }
-        TokenType::Underscore => {
-            let left = solve_node(node.operand1.as_ref().unwrap(), variables, defined_functions, pre_defined_functions).1;
-            let right = solve_node(node.operand2.as_ref().unwrap(), variables, defined_functions, pre_defined_functions).0;
-            if right < 0.0 || right > left.clone().unwrap().len() as f64 {
-                panic!("Index out of range in line {}", node.line);
-            }
-            (left.unwrap()[(right as usize) - 1], None)
+        Expr::Index { set, index: index_node, span } => {
+            let left = solve(set);
+            let right = solve(index_node);
+            let (Value::Set(set), Value::Number(index)) = (&left, &right) else {
+                raise(Diagnostic::new(format!("Expected a set and a number with operator _, got {} and {}", left.kind(), right.kind())).at(*span));
+            };
+            if *index < 1.0 || *index > set.len() as f64 {
+                raise(Diagnostic::new(format!("Index {} out of range for a set of {} elements", index, set.len())).at(index_node.span()));
+            }
+            Value::Number(set[(*index as usize) - 1])
         }
-        _ => {
-            panic!("Unsupported operation: {:?} in line {}", node.token.token_type, node.line);
+        Expr::Assign { span, .. } | Expr::FuncDef { span, .. } | Expr::Marker { span, .. } | Expr::Import { span, .. } => {
+            raise(Diagnostic::new("Expected a value, got a statement").at(*span));
         }
     }
 }
 
+/// Applies an arithmetic operator to two values, which must both be numbers.
+/// 
+/// # Arguments
+/// - `op`: The operator, used for error messages.
+/// - `span`: The span of the operation, used for error messages.
+/// - `left`: The value of the left operand.
+/// - `right`: The value of the right operand.
+/// - `operation`: The arithmetic to apply.
+/// 
+/// # Returns
+/// The result of the operation as a number.
+fn apply_numeric(op: BinaryOp, span: Span, left: &Value, right: &Value, operation: fn(f64, f64) -> f64) -> Value {
+    match (left, right) {
+        (Value::Number(x), Value::Number(y)) => Value::Number(operation(*x, *y)),
+        _ => raise(Diagnostic::new(format!("Expected operand1 and operand2 to be numbers with operator {}, got {} and {}", op, left.kind(), right.kind())).at(span)),
+    }
+}
+
+/// Compares two values of the same kind, sets being compared element by element.
+/// Sets of different lengths are unequal, and ordering them is an error.
+/// 
+/// # Arguments
+/// - `op`: The comparison.
+/// - `span`: The span of the comparison, used for error messages.
+/// - `left`: The value of the left operand.
+/// - `right`: The value of the right operand.
+/// 
+/// # Returns
+/// 1 if the comparison holds, otherwise 0.
+fn compare(op: BinaryOp, span: Span, left: &Value, right: &Value) -> Value {
+    let test: fn(CmpOrdering) -> bool = match op {
+        BinaryOp::Equal => |x| x == CmpOrdering::Equal,
+        // sets are unequal when any element differs
+        BinaryOp::NotEqual => return Value::Number(bool_as_f64(compare(BinaryOp::Equal, span, left, right).number() == FALSE)),
+        BinaryOp::Greater => |x| x == CmpOrdering::Greater,
+        BinaryOp::GreaterOrEqual => |x| x != CmpOrdering::Less,
+        BinaryOp::Less => |x| x == CmpOrdering::Less,
+        BinaryOp::LessOrEqual => |x| x != CmpOrdering::Greater,
+        _ => unreachable!("{} is not a comparison", op),
+    };
+    let result = match (left, right) {
+        (Value::Number(x), Value::Number(y)) => x.partial_cmp(y).is_some_and(test),
+        (Value::Set(x), Value::Set(y)) if x.len() == y.len() => x.iter().zip(y.iter()).all(|(a, b)| a.partial_cmp(b).is_some_and(test)),
+        (Value::Set(_), Value::Set(_)) if op == BinaryOp::Equal => false,
+        (Value::Set(x), Value::Set(y)) => {
+            let hint = Some("sets are ordered element by element, so they need the same number of elements".to_string());
+            raise(Diagnostic::new(format!("Expected sets of the same length with operator {}, got {} and {} elements", op, x.len(), y.len())).at(span).with_hint(hint))
+        }
+        (Value::Text(x), Value::Text(y)) => test(x.cmp(y)),
+        _ => raise(Diagnostic::new(format!("Expected operand1 and operand2 to be the same kind with operator {}, got {} and {}", op, left.kind(), right.kind())).at(span)),
+    };
+    Value::Number(bool_as_f64(result))
+}
+
+/// Reads a value used as a condition, which must be 0 or 1.
+/// 
+/// # Arguments
+/// - `value`: The value of the condition.
+/// - `span`: The span of the condition, used for error messages.
+/// - `what`: What the condition is, used for error messages.
+/// 
+/// # Returns
+/// Whether the value is 1.
+fn truth(value: Value, span: Span, what: &str) -> bool {
+    match value {
+        Value::Number(TRUE) => true,
+        Value::Number(FALSE) => false,
+        other => raise(Diagnostic::new(format!("Expected {} to have value 0 or 1, got {}", what, other)).at(span)),
+    }
+}
+
 /// Converts a boolean value to a floating-point number.
 ///
 /// # Arguments
@@ -485,21 +581,33 @@ pub fn bool_as_f64(b: bool) -> f64 {
 /// 
 /// # Arguments
 /// * `func` - A reference to the defined function to execute.
-/// * `parameters` - A vector of vectors containing optional expression nodes as parameters.
+/// * `arguments` - The argument expressions of the call.
 /// * `variables` - A reference to a vector of variables.
 /// * `defined_functions` - A reference to a vector of defined functions.
-/// * `pre_defined_functions` - A reference to a vector of pre-defined functions.
+/// * `context` - The native functions and the state of the run.
 /// 
 /// # Returns
 /// 
-/// Returns the result of executing the defined function as a floating-point number (f64).
-pub fn execute_defined_function(func: &DefinedFunction, parameters: Vec<Vec<Option<ExprNode>>>, variables: &Vec<Variable>, defined_functions: &Vec<DefinedFunction>, pre_defined_functions: &Vec<PreDefinedFunction>) -> (f64, Option<Vec<f64>>) {
-    if parameters.len() != func.parameters.as_ref().unwrap().len() {
-        panic!("Expected {} parameters, got {} in function: {}", func.parameters.as_ref().unwrap().len(), parameters.len(), func.name);
-    }
-    let mut params: Vec<(f64, Option<Vec<f64>>)> = Vec::new();
-    for p in parameters {
-        params.push(solve_node(&p[0].as_ref().unwrap(), variables.as_ref(), defined_functions, pre_defined_functions));
+/// Returns the result of executing the defined function.
+pub fn execute_defined_function(func: &DefinedFunction, arguments: &[Expr], variables: &Vec<Variable>, defined_functions: &Vec<DefinedFunction>, context: &Context) -> Value {
+    let params: Vec<Value> = arguments.iter().map(|x| solve_node(x, variables, defined_functions, context)).collect();
+    call_defined_function(func, params, variables, defined_functions, context)
+}
+
+/// Calls a defined function with already evaluated arguments.
+/// 
+/// # Arguments
+/// * `func` - A reference to the defined function to call.
+/// * `params` - The values bound to the function's parameters.
+/// * `variables` - A reference to a vector of variables, whose constants are visible to the function.
+/// * `defined_functions` - A reference to a vector of defined functions.
+/// * `context` - The native functions and the state of the run.
+/// 
+/// # Returns
+/// The result of the function body.
+pub fn call_defined_function(func: &DefinedFunction, params: Vec<Value>, variables: &[Variable], defined_functions: &Vec<DefinedFunction>, context: &Context) -> Value {
+    if params.len() != func.parameters.as_ref().unwrap().len() {
+        panic!("Expected {} parameters, got {} in function: {}", func.parameters.as_ref().unwrap().len(), params.len(), func.name);
     }
     let mut param_vars: Vec<Variable> = Vec::new();
     for (i, p) in params.iter().enumerate() {
@@ -508,44 +616,82 @@ pub fn execute_defined_function(func: &DefinedFunction, parameters: Vec<Vec<Opti
     for constant in variables.iter().filter(|x| x.is_constant) {
         param_vars.push(constant.clone());
     }
-    let node_box = func.function.clone();
-    let top_node = *node_box.unwrap();
-    solve_node(&top_node, &param_vars, defined_functions, pre_defined_functions)
+    context.counter.enter();
+    let value = solve_node(func.function.as_ref().unwrap(), &param_vars, defined_functions, context);
+    context.counter.leave();
+    value
 }
 
-/// Executes a pre-defined function with the provided parameters.
+/// Executes a native function with the provided parameters, raising the error it returns at the call.
 /// 
 /// # Arguments
-/// - `func`: A reference to the pre-defined function to be executed.
-/// - `parameters`: A vector of vectors containing optional expression nodes as parameters.
+/// - `func`: A reference to the native function to be executed.
+/// - `arguments`: The argument expressions of the call.
 /// - `variables`: A vector of variables used in the expression.
 /// - `defined_functions`: A vector of defined functions.
-/// - `pre_defined_functions`: A vector of pre-defined functions.
+/// - `context`: The native functions and the state of the run.
 /// 
 /// # Returns
-/// The result of the function execution as a floating-point number.
-pub fn execute_pre_defined_function(func: &PreDefinedFunction, parameters: Vec<Vec<Option<ExprNode>>>, variables: &Vec<Variable>, defined_functions: &Vec<DefinedFunction>, pre_defined_functions: &Vec<PreDefinedFunction>) -> f64 {
-    if parameters.len() != func.parameters as usize {
-        panic!("Expected {} parameters, got {} in function: {}", func.parameters, parameters.len(), func.name);
+/// The result of the function execution.
+pub fn execute_pre_defined_function(func: &NativeFunction, arguments: &[Expr], variables: &Vec<Variable>, defined_functions: &Vec<DefinedFunction>, context: &Context) -> Value {
+    if func.parameters >= 0 && arguments.len() != func.parameters as usize {
+        panic!("Expected {} parameters, got {} in function: {}", func.parameters, arguments.len(), func.name);
+    }
+    let params: Vec<Value> = arguments.iter().map(|x| solve_node(x, variables, defined_functions, context)).collect();
+    (func.execute)(&params).unwrap_or_else(|message| panic!("{}", message))
+}
+
+/// Executes a numeric function, passing its leading arguments as callable defined functions.
+/// 
+/// # Arguments
+/// - `func`: A reference to the numeric function to be executed.
+/// - `arguments`: The argument expressions of the call.
+/// - `variables`: A vector of variables used in the expression.
+/// - `defined_functions`: A vector of defined functions.
+/// - `context`: The native functions and the state of the run.
+/// 
+/// # Returns
+/// The result of the function execution.
+pub fn execute_numeric_function(func: &NumericFunction, arguments: &[Expr], variables: &Vec<Variable>, defined_functions: &Vec<DefinedFunction>, context: &Context) -> Value {
+    let mut callbacks: Vec<Callback> = Vec::new();
+    let mut params: Vec<Value> = Vec::new();
+    for argument in arguments {
+        let callee = match argument {
+            Expr::Variable { name, .. } if params.is_empty() => defined_functions.iter().find(|x| &x.name == name),
+            _ => None,
+        };
+        if let Some(callee) = callee {
+            callbacks.push(Box::new(move |args: &[f64]| {
+                let args = args.iter().map(|x| Value::Number(*x)).collect();
+                call_defined_function(callee, args, variables, defined_functions, context).number()
+            }));
+        }
+        else {
+            params.push(solve_node(argument, variables, defined_functions, context));
+        }
+    }
+    if func.functions < 0 && callbacks.is_empty() {
+        panic!("Expected at least 1 function name, got 0 in function: {}", func.name);
+    }
+    if func.functions >= 0 && callbacks.len() != func.functions as usize {
+        panic!("Expected {} function names, got {} in function: {}", func.functions, callbacks.len(), func.name);
     }
-    let mut params: Vec<(f64, Option<Vec<f64>>)> = Vec::new();
-    for (i, param) in parameters.iter().enumerate() {
-        params.push(solve_node(param[i].as_ref().unwrap(), variables, defined_functions, pre_defined_functions));
+    if !func.parameters.contains(&(params.len() as i32)) {
+        panic!("Expected {:?} parameters after the function names, got {} in function: {}", func.parameters, params.len(), func.name);
     }
-    (func.execute)(params)
+    (func.execute)(&callbacks, params, context.last_error)
 }
 
 /// Calculates the factorial of a given number.
 /// 
 /// # Arguments
-/// * `n` - The number to calculate the factorial of.
+/// * `n` - The number to calculate the factorial of, a whole number of at least 0.
 /// 
 /// # Returns
-/// The factorial of the given number.
+/// The factorial of the given number, infinity past 170 where it is too large for a number.
 pub fn factorial(n: f64) -> f64 {
-    if n == FALSE {
-        TRUE
-    } else {
-        n * factorial(n - TRUE)
+    if n > 170.0 {
+        return f64::INFINITY;
     }
+    (1..=n as u64).map(|x| x as f64).product()
 }
\ No newline at end of file
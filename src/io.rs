@@ -0,0 +1,224 @@
+use crate::interpreter::{character, concatenate, NativeFunction, Value};
+use crate::lexer::number_value;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::io::{stdin, stdout, Write};
+use std::rc::Rc;
+
+/// Where a session writes what scripts display and reads what they read, set with `Engine::set_io`.
+pub trait Io {
+    /// Writes text, without adding a line break.
+    fn write(&mut self, text: &str);
+    /// Reads a line without its line break, `None` once there is no more input.
+    fn read_line(&mut self) -> Option<String>;
+    /// Clears what has been written.
+    fn clear(&mut self);
+}
+
+/// Writes to standard output and reads from standard input, the default.
+pub struct StdIo;
+
+impl Io for StdIo {
+    fn write(&mut self, text: &str) {
+        print!("{}", text);
+        // so a prompt shows before reading
+        stdout().flush().unwrap_or(());
+    }
+    fn read_line(&mut self) -> Option<String> {
+        let mut buffer = String::new();
+        match stdin().read_line(&mut buffer) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(buffer.trim_end_matches(['\n', '\r']).to_string()),
+        }
+    }
+    fn clear(&mut self) {
+        println!("\x1B[2J\x1B[1;1H");
+    }
+}
+
+/// Keeps what is written in a buffer and reads from lines given up front, for tests and servers.
+#[derive(Debug, Default)]
+pub struct MemoryIo {
+    pub output: String,
+    input: VecDeque<String>
+}
+
+impl MemoryIo {
+    /// Creates a buffer reading the lines of `input`.
+    pub fn new(input: &str) -> Self {
+        MemoryIo { output: String::new(), input: input.lines().map(|x| x.to_string()).collect() }
+    }
+}
+
+impl Io for MemoryIo {
+    fn write(&mut self, text: &str) {
+        self.output += text;
+    }
+    fn read_line(&mut self) -> Option<String> {
+        self.input.pop_front()
+    }
+    fn clear(&mut self) {
+        self.output.clear();
+    }
+}
+
+/// Discards what is written and has no input.
+pub struct NullIo;
+
+impl Io for NullIo {
+    fn write(&mut self, _: &str) {}
+    fn read_line(&mut self) -> Option<String> {
+        None
+    }
+    fn clear(&mut self) {}
+}
+
+/// What the I/O functions of a session share.
+struct IoState {
+    /// Whether the input has ended.
+    ended: Cell<bool>,
+    /// The significant digits numbers are displayed with, set by `precision()`.
+    precision: Rc<Cell<usize>>
+}
+
+/// The body of a function using the session's I/O.
+type IoFunction = fn(&[Value], &mut dyn Io, &IoState) -> Result<Value, String>;
+
+/// Returns the functions displaying, reading and clearing, which use the given I/O and display numbers with the
+/// given precision. Reading at the end of the input returns 0 or an empty set, and `eof()` returns 1 from then on.
+pub fn get_io_functions(io: &Rc<RefCell<dyn Io>>, precision: &Rc<Cell<usize>>) -> Vec<NativeFunction> {
+    let state = Rc::new(IoState { ended: Cell::new(false), precision: precision.clone() });
+    let native = |name: &str, parameters: i32, execute: IoFunction| {
+        let io = io.clone();
+        let state = state.clone();
+        NativeFunction { name: name.to_string(), parameters, execute: Box::new(move |v| execute(v, &mut *io.borrow_mut(), &state)) }
+    };
+    vec![
+        native("displayln", -1, |v, io, state| {
+            io.write(&format!("{}\n", concatenate(v, state.precision.get())));
+            Ok(Value::Number(0.0))
+        }),
+        native("display", -1, |v, io, state| {
+            io.write(&concatenate(v, state.precision.get()));
+            Ok(Value::Number(0.0))
+        }),
+        native("dacln", 1, |v, io, _| {
+            io.write(&format!("{}\n", character(v[0].number(), "dacln")));
+            Ok(Value::Number(0.0))
+        }),
+        native("dac", 1, |v, io, _| {
+            io.write(&character(v[0].number(), "dac").to_string());
+            Ok(Value::Number(0.0))
+        }),
+        native("read", -1, |v, io, state| {
+            let number = prompt(v, io, state, "read", |line| {
+                match parse_number(line) {
+                    Some(number) => Ok(number),
+                    None if line.is_empty() => Err("Expected a number, got nothing".to_string()),
+                    None => Err(format!("Expected a number, got '{}'", line)),
+                }
+            })?;
+            Ok(Value::Number(number.unwrap_or(0.0)))
+        }),
+        native("readset", -1, |v, io, state| {
+            let set = prompt(v, io, state, "readset", |line| {
+                let inner = line.strip_prefix('{').and_then(|x| x.strip_suffix('}')).unwrap_or(line).trim();
+                if inner.is_empty() {
+                    return Ok(vec![]);
+                }
+                inner.split(',').map(|x| parse_number(x.trim()).ok_or_else(|| format!("Expected numbers separated by commas, got '{}'", x.trim()))).collect()
+            })?;
+            Ok(Value::Set(set.unwrap_or_default()))
+        }),
+        native("eof", 0, |_, _, state| {
+            Ok(Value::Number(if state.ended.get() { 1.0 } else { 0.0 }))
+        }),
+        native("clear", 0, |_, io, _| {
+            io.clear();
+            Ok(Value::Number(0.0))
+        }),
+    ]
+}
+
+/// Writes an optional prompt and reads a line, writing what was wrong with invalid input and asking again.
+///
+/// # Arguments
+/// * `arguments` - The arguments of the call, the prompt if there is one.
+/// * `io` - Where to write the prompt and read the line.
+/// * `state` - Whether the input has ended, set once there is no more, and the precision the prompt is written with.
+/// * `name` - The name of the calling function, for error messages.
+/// * `parse` - Reads the value from a line, or says what is wrong with it.
+///
+/// # Returns
+/// The value read, or `None` at the end of the input.
+fn prompt<T>(arguments: &[Value], io: &mut dyn Io, state: &IoState, name: &str, parse: impl Fn(&str) -> Result<T, String>) -> Result<Option<T>, String> {
+    if arguments.len() > 1 {
+        return Err(format!("Expected 0 or 1 parameters, got {} in function: {}", arguments.len(), name));
+    }
+    loop {
+        if let Some(prompt) = arguments.first() {
+            io.write(&prompt.display(state.precision.get()));
+        }
+        let Some(line) = io.read_line() else {
+            state.ended.set(true);
+            return Ok(None);
+        };
+        match parse(line.trim()) {
+            Ok(value) => return Ok(Some(value)),
+            Err(message) => io.write(&format!("{}\n", message)),
+        }
+    }
+}
+
+/// Reads a number typed as input, written as in a script with an optional sign.
+fn parse_number(text: &str) -> Option<f64> {
+    let (sign, digits) = match text.strip_prefix('-') {
+        Some(digits) => (-1.0, digits),
+        None => (1.0, text.strip_prefix('+').unwrap_or(text)),
+    };
+    // leaves out "inf" and "nan", which Rust reads as numbers
+    if !digits.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+        return None;
+    }
+    number_value(digits).map(|x| sign * x)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::run_script;
+    use crate::limits::Limits;
+
+    fn run(script: &str, input: &str) -> String {
+        run_script(script, input, Limits::default()).unwrap_or_else(|errors| panic!("{:?}", errors))
+    }
+
+    #[test]
+    fn display_and_dac_write_to_the_io() {
+        assert_eq!(run("display(1, \" \", {1, 2}), displayln(\"!\"), dac(72), dacln(105)", ""), "1 {1, 2}!\nHi\n");
+        assert_eq!(run("displayln(1), clear(), display(2)", ""), "2");
+    }
+
+    #[test]
+    fn read_prompts_and_asks_again_on_invalid_input() {
+        let output = run("read(\"X: \") -> X, read() -> Y, displayln(X + Y)", "abc\n\n-1.5\n0x10");
+        assert_eq!(output, "X: Expected a number, got 'abc'\nX: Expected a number, got nothing\nX: 14.5\n");
+    }
+
+    #[test]
+    fn readset_reads_numbers_separated_by_commas() {
+        let output = run("readset() -> S, readset() -> T, displayln(S, len(T))", "1, x\n{1, 2.5, -3}\n{}");
+        assert_eq!(output, "Expected numbers separated by commas, got 'x'\n{1, 2.5, -3}0\n");
+    }
+
+    #[test]
+    fn eof_is_set_once_the_input_ends() {
+        let output = run("displayln(eof(), read()), displayln(eof(), read(), readset(), eof())", "4");
+        assert_eq!(output, "04\n00{}1\n");
+    }
+
+    #[test]
+    fn read_checks_its_parameters() {
+        let errors = run_script("read(1, 2)", "", Limits::default()).unwrap_err();
+        assert_eq!(errors[0].message, "Expected 0 or 1 parameters, got 2 in function: read");
+    }
+}
@@ -1,78 +1,73 @@
-use core::panic;
-use std::io::{self, Result};
+use crate::diagnostic::Diagnostic;
+use crate::symbols::{self, ROOT, SUPERSCRIPT_MINUS};
+use std::fmt;
+use std::str::CharIndices;
 
 #[derive(Clone, Debug, PartialEq)] 
-pub(crate) enum TokenType {
+pub enum TokenType {
     Arrow, Plus, Dash, Star, Slash, Equal, Percantage, Carrot,
     OpenParen, CloseParen, OpenCurley, CloseCurley, DoubleArrow,
     Colon, Semicolon, GreaterThan, LessThan, Comma, Dot, Exclamation,
     GreaterThanOrEqualTo, LessThanOrEqualTo, DollarSign, Underscore,
-    Number, Uppercase, Lowercase, None
+    NotEqual, And, Or, Xor, Not, Root, Continuation, Doc, Import,
+    Number, Uppercase, Lowercase, Text
 }
-impl TokenType {
-    pub fn to_string(&self) -> String {
-        match self {
-            TokenType::Arrow => "Arrow".to_string(),
-            TokenType::DoubleArrow => "DoubleArrow".to_string(),
-            TokenType::Plus => "Plus".to_string(),
-            TokenType::Dash => "Dash".to_string(),
-            TokenType::Star => "Star".to_string(),
-            TokenType::Slash => "Slash".to_string(),
-            TokenType::Equal => "Equal".to_string(),
-            TokenType::Percantage => "Percantage".to_string(),
-            TokenType::Carrot => "Carrot".to_string(),
-            TokenType::OpenParen => "OpenParen".to_string(),
-            TokenType::CloseParen => "CloseParen".to_string(),
-            TokenType::OpenCurley => "OpenCurley".to_string(),
-            TokenType::CloseCurley => "CloseCurley".to_string(),
-            TokenType::Colon => "Colon".to_string(),
-            TokenType::Semicolon => "Semicolon".to_string(),
-            TokenType::GreaterThan => "GreaterThan".to_string(),
-            TokenType::GreaterThanOrEqualTo => "GreaterThanOrEqualTo".to_string(),
-            TokenType::LessThan => "LessThan".to_string(),
-            TokenType::LessThanOrEqualTo => "LessThanOrEqualTo".to_string(),
-            TokenType::Comma => "Comma".to_string(),
-            TokenType::Dot => "Dot".to_string(),
-            TokenType::Exclamation => "Exclamation".to_string(),
-            TokenType::Number => "Number".to_string(),
-            TokenType::Uppercase => "Uppercase".to_string(),
-            TokenType::Lowercase => "Lowercase".to_string(),
-            TokenType::None => "None".to_string(),
-            TokenType::DollarSign => "DollarSign".to_string(),
-            TokenType::Underscore => "Underscore".to_string(),
-        }
+impl fmt::Display for TokenType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TokenType::Arrow => "Arrow",
+            TokenType::DoubleArrow => "DoubleArrow",
+            TokenType::Plus => "Plus",
+            TokenType::Dash => "Dash",
+            TokenType::Star => "Star",
+            TokenType::Slash => "Slash",
+            TokenType::Equal => "Equal",
+            TokenType::Percantage => "Percantage",
+            TokenType::Carrot => "Carrot",
+            TokenType::OpenParen => "OpenParen",
+            TokenType::CloseParen => "CloseParen",
+            TokenType::OpenCurley => "OpenCurley",
+            TokenType::CloseCurley => "CloseCurley",
+            TokenType::Colon => "Colon",
+            TokenType::Semicolon => "Semicolon",
+            TokenType::GreaterThan => "GreaterThan",
+            TokenType::GreaterThanOrEqualTo => "GreaterThanOrEqualTo",
+            TokenType::LessThan => "LessThan",
+            TokenType::LessThanOrEqualTo => "LessThanOrEqualTo",
+            TokenType::Comma => "Comma",
+            TokenType::Dot => "Dot",
+            TokenType::Exclamation => "Exclamation",
+            TokenType::NotEqual => "NotEqual",
+            TokenType::And => "And",
+            TokenType::Or => "Or",
+            TokenType::Xor => "Xor",
+            TokenType::Not => "Not",
+            TokenType::Root => "Root",
+            TokenType::Continuation => "Continuation",
+            TokenType::Doc => "Doc",
+            TokenType::Import => "Import",
+            TokenType::Number => "Number",
+            TokenType::Uppercase => "Uppercase",
+            TokenType::Lowercase => "Lowercase",
+            TokenType::Text => "Text",
+            TokenType::DollarSign => "DollarSign",
+            TokenType::Underscore => "Underscore",
+        };
+        write!(f, "{}", name)
     }
-    pub fn is_operator(&self) -> bool {
-        match self {
-            TokenType::Arrow => false,
-            TokenType::DoubleArrow => false,
-            TokenType::Plus => true,
-            TokenType::Dash => true,
-            TokenType::Star => true,
-            TokenType::Slash => true,
-            TokenType::Equal => true,
-            TokenType::Percantage => true,
-            TokenType::Carrot => true,
-            TokenType::OpenParen => false,
-            TokenType::CloseParen => false,
-            TokenType::OpenCurley => false,
-            TokenType::CloseCurley => false,
-            TokenType::Colon => false,
-            TokenType::Semicolon => false,
-            TokenType::GreaterThan => true,
-            TokenType::GreaterThanOrEqualTo => true,
-            TokenType::LessThan => true,
-            TokenType::LessThanOrEqualTo => true,
-            TokenType::Comma => false,
-            TokenType::Dot => false,
-            TokenType::Exclamation => false,
-            TokenType::Number => false,
-            TokenType::Uppercase => false,
-            TokenType::Lowercase => false,
-            TokenType::None => false,
-            TokenType::DollarSign => false,
-            TokenType::Underscore => false,
-        }
+}
+/// A location in the source: byte offset and length into the contents, and the physical line and column (both starting at 1).
+/// `file` is the index of the file in `diagnostic::add_file`, 0 being the script that was run.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Span {
+    pub offset: usize, pub length: usize, pub line: i32, pub column: usize, pub file: usize
+}
+impl Span {
+    /// Returns the span covering both spans, from the start of the earlier to the end of the later.
+    pub fn to(&self, other: Span) -> Span {
+        let (first, last) = if self.offset <= other.offset { (self, &other) } else { (&other, self) };
+        let end = (first.offset + first.length).max(last.offset + last.length);
+        Span { length: end - first.offset, ..*first }
     }
 }
 
@@ -82,236 +77,507 @@ pub struct Line {
 }
 #[derive(Clone, Debug, PartialEq)] 
 pub struct Token {
-    pub token_type: TokenType, pub value: String
+    pub token_type: TokenType, pub value: String, pub span: Span
 }
 /// Processes the contents to extract lexer lines.
 /// 
 /// # Arguments
 /// * `contents` - A string containing the contents to be processed.
+/// * `file` - The index of the file the contents were read from.
 /// 
 /// # Returns
-/// A vector of `Line` structs representing the lexer lines extracted from the contents.
-pub fn get_lexer_lines(contents: &str) -> Vec<Line> {
-    let lines: Vec<&str> = contents.split("\n").filter(|&x| x.trim() != "").collect();
+/// A vector of `Line` structs representing the lexer lines extracted from the contents, and the errors found in them.
+/// Lines without tokens are skipped, but line numbers still match the file. Lines with an error are left out, so
+/// the parser only reports errors of its own.
+/// A line continues onto the next while a parenthesis or brace is open, or when it ends with `\`. An open bracket
+/// stops continuing at a blank line or a line that cannot continue the statement, leaving the parser to report it.
+/// A doc comment starts the line after it, so it documents the function defined there.
+pub fn get_lexer_lines(contents: &str, file: usize) -> (Vec<Line>, Vec<Diagnostic>) {
+    let mut lexer = Lexer::new(contents, file);
     let mut lexer_lines: Vec<Line> = Vec::new();
-    
-    // return lexer lines
-    let mut line_number = 0;
-    for line in lines {
-        line_number += 1;
-        let l = get_lexer_line(line, line_number);
-        lexer_lines.push(l);
-    }
-    lexer_lines
+    let mut last_line = 0;
+    let mut depth = 0;
+    let mut previous: Option<Token> = None;
+    let mut continuation: Option<Token> = None;
+    let mut doc: Option<Token> = None;
+    let mut errors: Vec<Diagnostic> = Vec::new();
+
+    for token in lexer.by_ref() {
+        if token.token_type == TokenType::Doc {
+            // inside a statement it is only a comment
+            if depth == 0 && continuation.is_none() {
+                doc = Some(token);
+            }
+            continue;
+        }
+        if let Some(marker) = continuation.take() {
+            if token.span.line == marker.span.line {
+                errors.push(Diagnostic::new("Expected '\\' to be at the end of line, continuing it onto the next").at(marker.span));
+            }
+        }
+        else if token.span.line != last_line && (depth == 0 || !continues(contents, previous.as_ref(), &token)) {
+            depth = 0;
+            lexer_lines.push(Line { number: token.span.line, tokens: doc.take().into_iter().collect() });
+        }
+        last_line = token.span.line;
+
+        match token.token_type {
+            TokenType::Continuation => {
+                continuation = Some(token);
+                continue;
+            }
+            TokenType::OpenParen | TokenType::OpenCurley => depth += 1,
+            TokenType::CloseParen | TokenType::CloseCurley if depth > 0 => depth -= 1,
+            _ => {}
+        }
+        previous = Some(token.clone());
+        match lexer_lines.last_mut() {
+            Some(line) => line.tokens.push(token),
+            None => lexer_lines.push(Line { number: token.span.line, tokens: vec![token] }),
+        }
+    }
+    errors.extend(lexer.into_errors());
+    errors.sort_by_key(|x| x.span.map(|x| x.offset));
+    lexer_lines.retain(|line| {
+        let last = line.tokens.iter().map(|x| x.span.line).max().unwrap_or(line.number);
+        !errors.iter().flat_map(|x| x.span).any(|x| x.line >= line.number && x.line <= last)
+    });
+    (lexer_lines, errors)
 }
 
-/// Parses a line of text into a collection of tokens representing different types of symbols, numbers, and characters.
-/// 
-/// # Arguments
-/// * `line` - The input line of text to be parsed.
-/// * `line_number` - The line number of the input line.
-/// 
-/// # Returns
-/// A `Line` struct containing the parsed tokens and the line number.
-pub fn get_lexer_line(line: &str, line_number: i32, ) -> Line {
-    let chars: Vec<&str> = line.split("").collect();
-    let mut tokens: Vec<Token> = Vec::new();
-    let mut single: bool = false;
-    let mut skip: usize = 0;
-    let mut number: String = String::new();
-    let mut alphabetical: String = String::new();
+/// Returns whether a token starting a physical line continues the statement of an open bracket before it.
+/// It does not after a blank line, when it starts a statement of its own, or when a value would follow a value.
+fn continues(contents: &str, previous: Option<&Token>, token: &Token) -> bool {
+    let Some(previous) = previous else {
+        return false;
+    };
+    let gap = &contents[previous.span.offset + previous.span.length..token.span.offset];
+    let pieces: Vec<&str> = gap.split('\n').collect();
+    if pieces.len() > 2 && pieces[1..pieces.len() - 1].iter().any(|x| x.trim().is_empty()) {
+        return false;
+    }
+    let ends_value = matches!(previous.token_type, TokenType::Number | TokenType::Uppercase | TokenType::Lowercase |
+        TokenType::Text | TokenType::CloseParen | TokenType::CloseCurley | TokenType::Exclamation);
+    match token.token_type {
+        TokenType::DollarSign | TokenType::Import => false,
+        TokenType::Number | TokenType::Uppercase | TokenType::Lowercase | TokenType::Text | TokenType::OpenParen |
+        TokenType::OpenCurley | TokenType::Not | TokenType::Root => !ends_value,
+        _ => true,
+    }
+}
 
-    // loop through chars
-    for (j, &c) in chars.iter().enumerate() {
-        if skip > 0 { 
-            skip -= 1;
-            continue;
+/// A scanner over the characters of the contents, producing tokens with their spans one at a time.
+pub struct Lexer<'a> {
+    source: &'a str,
+    chars: CharIndices<'a>,
+    /// Line and column (counted in characters) of the next character.
+    line: i32,
+    column: usize,
+    file: usize,
+    /// End of a run of uppercase letters already checked, each letter being its own variable.
+    uppercase_until: usize,
+    /// Tokens already scanned from one symbol, such as the "^" and "2" of "²", last first.
+    pending: Vec<Token>,
+    /// Errors found so far. The characters in error are skipped and scanning goes on.
+    errors: Vec<Diagnostic>
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str, file: usize) -> Self {
+        Lexer { source, chars: source.char_indices(), line: 1, column: 1, file, uppercase_until: 0, pending: Vec::new(), errors: Vec::new() }
+    }
+
+    /// Returns the errors found while scanning, once the tokens have been read.
+    pub fn into_errors(self) -> Vec<Diagnostic> {
+        self.errors
+    }
+
+    /// Byte offset of the next character.
+    fn offset(&self) -> usize {
+        self.chars.offset()
+    }
+    /// Returns the character `n` places ahead without consuming anything.
+    fn peek_at(&self, n: usize) -> Option<char> {
+        self.chars.clone().nth(n).map(|(_, c)| c)
+    }
+    fn peek(&self) -> Option<char> {
+        self.peek_at(0)
+    }
+    /// Consumes the next character, keeping track of the line and column.
+    fn bump(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
         }
+        else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+    /// Consumes characters while they match.
+    fn bump_while(&mut self, test: impl Fn(char) -> bool) {
+        while self.peek().is_some_and(&test) {
+            self.bump();
+        }
+    }
+    /// Returns an empty span at the next character.
+    fn here(&self) -> Span {
+        Span { offset: self.offset(), length: 0, line: self.line, column: self.column, file: self.file }
+    }
+    /// Returns the span from a start position up to the next character.
+    fn span_from(&self, start: Span) -> Span {
+        Span { length: self.offset() - start.offset, ..start }
+    }
+    fn token(&self, token_type: TokenType, start: Span) -> Token {
+        let span = self.span_from(start);
+        Token { token_type, value: self.source[span.offset..span.offset + span.length].to_string(), span }
+    }
 
-        // is number
-        if let Ok(_) = parse_str_to_i32(c) {
-            number += c;
+    /// Scans a number: decimal with an optional exponent such as "1e-9", hexadecimal "0xFF" or binary "0b1010",
+    /// with "_" between digits separating them. A lowercase "e" not followed by digits is the constant, so "2e" is 2 times e.
+    fn number(&mut self, start: Span) -> Token {
+        let first = self.bump().unwrap();
+        let radix = match (first, self.peek()) {
+            ('0', Some('x')) => 16,
+            ('0', Some('b')) => 2,
+            _ => 10,
+        };
+        if radix != 10 && self.peek_at(1).is_some_and(|c| c.is_digit(radix)) {
+            self.bump();
+            self.digits(radix);
+            return self.token(TokenType::Number, start);
         }
-        // is alphabetical
-        else if is_alphabetical(c) {
-            alphabetical += c;
+
+        self.digits(10);
+        // a "." not starting a "..." comment
+        while self.peek() == Some('.') && !(self.peek_at(1) == Some('.') && self.peek_at(2) == Some('.')) {
+            self.bump();
+            self.digits(10);
         }
-        // is dot
-        else if c == "." {
-            // and is number
-            if number != "".to_string() {
-                number += c;
-            }
-            // and next is number
-            else if chars.len() > j + 1 && parse_str_to_i32(&chars[j + 1].to_string()).is_ok() {
-                number += c;
-            }
-            // and is apart of comment
-            else if chars.len() > j + 2 && chars[j + 1] == "." && chars[j + 2] == "." {
-                break;
-            }
-            // and by itself 
-            else {
-                tokens.push(Token { token_type: TokenType::Dot, value: c.to_string() });
-                single = true;
+        let sign = matches!(self.peek_at(1), Some('+' | '-'));
+        if self.peek() == Some('e') && self.peek_at(1 + usize::from(sign)).is_some_and(|c| c.is_ascii_digit()) {
+            self.bump();
+            if sign {
+                self.bump();
             }
+            self.digits(10);
         }
-        // is dash
-        else if c == "-" {
-            // and apart of arrow
-            if chars.len() > j + 1 && chars[j + 1] == ">" {
-                tokens.push(Token { token_type: TokenType::Arrow, value: "->".to_string() });
-                skip = 2;
-            }
-            // and by itself 
-            else {
-                tokens.push(Token { token_type: TokenType::Dash, value: c.to_string() });
-                single = true;
+        self.token(TokenType::Number, start)
+    }
+    /// Consumes digits of a radix, and "_" between two of them.
+    fn digits(&mut self, radix: u32) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_digit(radix) => {}
+                Some('_') if self.peek_at(1).is_some_and(|c| c.is_digit(radix)) => {}
+                _ => return,
             }
+            self.bump();
         }
-        // is dash
-        else if c == "-" {
-            // and apart of arrow
-            if chars.len() > j + 1 && chars[j + 1] == ">" {
-                tokens.push(Token { token_type: TokenType::Arrow, value: "->".to_string() });
-                skip = 2;
-            }
-            // and by itself 
-            else {
-                tokens.push(Token { token_type: TokenType::Dash, value: c.to_string() });
-                single = true;
-            }
+    }
+
+    /// Scans a name: a function or constant (all lowercase), a word operator, or one variable (uppercase) of a run such as "AC".
+    fn name(&mut self, start: Span) -> Option<Token> {
+        if start.offset < self.uppercase_until {
+            self.bump();
+            return Some(self.token(TokenType::Uppercase, start));
         }
-        // is equal
-        else if c == "=" {
-            // and apart of double arrow
-            if chars.len() > j + 1 && chars[j + 1] == ">" {
-                tokens.push(Token { token_type: TokenType::DoubleArrow, value: "=>".to_string() });
-                skip = 2;
-            }
-            // and by itself 
-            else {
-                tokens.push(Token { token_type: TokenType::Equal, value: c.to_string() });
-                single = true;
-            }
+        let rest = &self.source[start.offset..];
+        let end = rest.find(|c: char| !c.is_alphabetic() || symbols::alias(c).is_some()).unwrap_or(rest.len());
+        let word = &rest[..end];
+
+        if word.chars().all(|c| c.is_uppercase()) {
+            self.uppercase_until = start.offset + end;
+            self.bump();
+            Some(self.token(TokenType::Uppercase, start))
+        }
+        else if word.chars().all(|c| c.is_lowercase()) {
+            self.bump_while(|c| c.is_alphabetic() && symbols::alias(c).is_none());
+            let token_type = match word {
+                "and" => TokenType::And,
+                "or" => TokenType::Or,
+                "xor" => TokenType::Xor,
+                "not" => TokenType::Not,
+                "import" => TokenType::Import,
+                _ => TokenType::Lowercase,
+            };
+            Some(self.token(token_type, start))
+        }
+        else {
+            let hint = Some("functions and constants are all lowercase, variables all uppercase".to_string());
+            let span = Span { length: end, ..start };
+            self.errors.push(Diagnostic::new(format!("Unknown name '{}', expects either function (all lowercase) or variable (all uppercase)", word)).at(span).with_hint(hint));
+            self.bump_while(|c| c.is_alphabetic() && symbols::alias(c).is_none());
+            None
         }
-        // is less than
-        else if c == "<" {
-            // and apart of less than or equal to
-            if chars.len() > j + 1 && chars[j + 1] == "=" {
-                tokens.push(Token { token_type: TokenType::LessThanOrEqualTo, value: "<=".to_string() });
-                skip = 2;
+    }
+
+    /// Scans text between quotes, unescaping as it goes.
+    fn text(&mut self, start: Span) -> Option<Token> {
+        self.bump();
+        let mut text = String::new();
+        loop {
+            let position = self.here();
+            match self.bump() {
+                Some('"') => break,
+                Some('\\') => match self.bump() {
+                    Some('n') => text.push('\n'),
+                    Some('t') => text.push('\t'),
+                    Some('"') => text.push('"'),
+                    Some('\\') => text.push('\\'),
+                    other => {
+                        let hint = Some("escapes are \\n, \\t, \\\" and \\\\".to_string());
+                        self.errors.push(Diagnostic::new(format!("Unknown escape '\\{}'", other.map_or(String::new(), String::from))).at(self.span_from(position)).with_hint(hint));
+                    }
+                },
+                Some('\n') | None => {
+                    self.errors.push(Diagnostic::new("Unterminated text, expected closing '\"'").at(Span { length: position.offset - start.offset, ..start }));
+                    return None;
+                }
+                Some(c) => text.push(c),
             }
-            // and by itself 
-            else {
-                tokens.push(Token { token_type: TokenType::LessThan, value: c.to_string() });
-                single = true;
+        }
+        Some(Token { token_type: TokenType::Text, value: text, span: self.span_from(start) })
+    }
+
+    /// Returns the tokens of the ASCII a Unicode symbol stands for, such as "*" for "×", all spanning the symbol.
+    fn expand(&mut self, start: Span, ascii: &str) -> Token {
+        let span = self.span_from(start);
+        let mut tokens: Vec<Token> = Lexer::new(ascii, self.file).map(|x| Token { span, ..x }).collect();
+        let first = tokens.remove(0);
+        self.pending.extend(tokens.into_iter().rev());
+        first
+    }
+
+    /// Scans a power written in superscript, such as "²" or "⁻¹", as "^" followed by the power.
+    fn superscript(&mut self, start: Span) -> Option<Token> {
+        let mut power = String::new();
+        while let Some(c) = self.peek() {
+            match symbols::superscript_digit(c) {
+                Some(digit) => power.push(digit),
+                None if c == SUPERSCRIPT_MINUS && power.is_empty() => power.push('-'),
+                None => break,
             }
+            self.bump();
+        }
+        if !power.ends_with(|c: char| c.is_ascii_digit()) {
+            self.errors.push(Diagnostic::new("Expected superscript digits after '⁻'").at(self.span_from(start)));
+            return None;
         }
-        // is less than
-        else if c == ">" {
-            // and apart of greater than or equal to
-            if chars.len() > j + 1 && chars[j + 1] == "=" {
-                tokens.push(Token { token_type: TokenType::GreaterThanOrEqualTo, value: ">=".to_string() });
-                skip = 2;
+        Some(self.expand(start, &format!("^{}", power)))
+    }
+
+    /// Scans a block comment between "/*" and "*/", which can span lines.
+    ///
+    /// # Returns
+    /// A doc comment token for comments starting with "/**", with the text cleaned of the markers and leading "*"s.
+    fn block_comment(&mut self, start: Span) -> Option<Token> {
+        self.bump();
+        self.bump();
+        let is_doc = self.peek() == Some('*') && self.peek_at(1) != Some('/');
+        let text_start = self.offset() + usize::from(is_doc);
+        loop {
+            if self.peek() == Some('*') && self.peek_at(1) == Some('/') {
+                break;
             }
-            // and by itself 
-            else {
-                tokens.push(Token { token_type: TokenType::GreaterThan, value: c.to_string() });
-                single = true;
+            if self.bump().is_none() {
+                self.errors.push(Diagnostic::new("Unterminated comment, expected closing '*/'").at(Span { length: 2, ..start }));
+                return None;
             }
         }
-        // symbols:
-        else if c == "+" { tokens.push(Token { token_type: TokenType::Plus, value: c.to_string() }); single = true; }
-        else if c == "*" { tokens.push(Token { token_type: TokenType::Star, value: c.to_string() }); single = true; }
-        else if c == "/" { tokens.push(Token { token_type: TokenType::Slash, value: c.to_string() }); single = true; }
-        else if c == "^" { tokens.push(Token { token_type: TokenType::Carrot, value: c.to_string() }); single = true; }
-        else if c == "%" { tokens.push(Token { token_type: TokenType::Percantage, value: c.to_string() }); single = true; }
-        else if c == "!" { tokens.push(Token { token_type: TokenType::Exclamation, value: c.to_string() }); single = true; }
-        else if c == "(" { tokens.push(Token { token_type: TokenType::OpenParen, value: c.to_string() }); single = true; }
-        else if c == ")" { tokens.push(Token { token_type: TokenType::CloseParen, value: c.to_string() }); single = true; }
-        else if c == "{" { tokens.push(Token { token_type: TokenType::OpenCurley, value: c.to_string() }); single = true; }
-        else if c == "}" { tokens.push(Token { token_type: TokenType::CloseCurley, value: c.to_string() }); single = true; }
-        else if c == ";" { tokens.push(Token { token_type: TokenType::Semicolon, value: c.to_string() }); single = true; }
-        else if c == ":" { tokens.push(Token { token_type: TokenType::Colon, value: c.to_string() }); single = true; }
-        else if c == "," { tokens.push(Token { token_type: TokenType::Comma, value: c.to_string() }); single = true; }
-        else if c == "$" { tokens.push(Token { token_type: TokenType::DollarSign, value: c.to_string() }); single = true; }
-        else if c == "_" { tokens.push(Token { token_type: TokenType::Underscore, value: c.to_string() }); single = true; }
-        // spaces:
-        if c == " " || chars.len() == j + 1 || single {
-            let mut tok: Token = Token { token_type:TokenType::None, value:String::new() };
-            if single {
-                tok = tokens.last().unwrap().clone();
-                tokens.remove(tokens.len() - 1);
-            }
-            if !number.is_empty() {
-                tokens.push(Token { token_type: TokenType::Number, value: number.to_string() });
-                number = String::new();
+        let text_end = self.offset();
+        self.bump();
+        self.bump();
+        if !is_doc {
+            return None;
+        }
+
+        let lines: Vec<&str> = self.source[text_start..text_end].lines()
+            .map(|x| x.trim())
+            .map(|x| x.strip_prefix('*').map_or(x, |x| x.trim_start()))
+            .collect();
+        let text = lines.join("\n").trim().to_string();
+        Some(Token { token_type: TokenType::Doc, value: text, span: self.span_from(start) })
+    }
+
+    /// Scans an operator or punctuation, preferring the two character operators.
+    fn symbol(&mut self, start: Span) -> Option<Token> {
+        let first = self.bump().unwrap();
+        let double = match (first, self.peek()) {
+            ('-', Some('>')) => Some(TokenType::Arrow),
+            ('=', Some('>')) => Some(TokenType::DoubleArrow),
+            ('<', Some('=')) => Some(TokenType::LessThanOrEqualTo),
+            ('>', Some('=')) => Some(TokenType::GreaterThanOrEqualTo),
+            ('<', Some('>')) | ('!', Some('=')) => Some(TokenType::NotEqual),
+            ('&', Some('&')) => Some(TokenType::And),
+            ('|', Some('|')) => Some(TokenType::Or),
+            _ => None,
+        };
+        if let Some(token_type) = double {
+            self.bump();
+            return Some(self.token(token_type, start));
+        }
+        let token_type = match first {
+            '+' => TokenType::Plus,
+            '-' => TokenType::Dash,
+            '*' => TokenType::Star,
+            '/' => TokenType::Slash,
+            '^' => TokenType::Carrot,
+            '%' => TokenType::Percantage,
+            '!' => TokenType::Exclamation,
+            '=' => TokenType::Equal,
+            '<' => TokenType::LessThan,
+            '>' => TokenType::GreaterThan,
+            '(' => TokenType::OpenParen,
+            ')' => TokenType::CloseParen,
+            '{' => TokenType::OpenCurley,
+            '}' => TokenType::CloseCurley,
+            ';' => TokenType::Semicolon,
+            ':' => TokenType::Colon,
+            ',' => TokenType::Comma,
+            '.' => TokenType::Dot,
+            '$' => TokenType::DollarSign,
+            '_' => TokenType::Underscore,
+            '\\' => TokenType::Continuation,
+            other => {
+                self.errors.push(Diagnostic::new(format!("Unknown character '{}'", other)).at(self.span_from(start)));
+                return None;
             }
-            if !alphabetical.is_empty() {
-                if alphabetical.chars().all(|c| c.is_uppercase()) {
-                    for alph in alphabetical.chars() {
-                        tokens.push(Token { token_type: TokenType::Uppercase, value: alph.to_string() });
-                    }
+        };
+        Some(self.token(token_type, start))
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if let Some(token) = self.pending.pop() {
+            return Some(token);
+        }
+        loop {
+            let c = self.peek()?;
+            let start = self.here();
+            let token = match c {
+                c if c.is_whitespace() => {
+                    self.bump();
+                    continue;
                 }
-                else if alphabetical.chars().all(|c| c.is_lowercase()) {
-                    tokens.push(Token { token_type: TokenType::Lowercase, value: alphabetical.to_string() });
+                // comment until the end of the line
+                '.' if self.peek_at(1) == Some('.') && self.peek_at(2) == Some('.') => {
+                    self.bump_while(|c| c != '\n');
+                    continue;
                 }
-                else {
-                    panic!("Unkown token_type '{}', expects either function (all lowercase) or variable (all uppercase)", alphabetical);
+                '/' if self.peek_at(1) == Some('*') => self.block_comment(start),
+                c if symbols::alias(c).is_some() => {
+                    self.bump();
+                    Some(self.expand(start, symbols::alias(c).unwrap()))
                 }
-                alphabetical = String::new();
-            }
-            if single {
-                single = false;
-                tokens.push(tok);
+                c if c == SUPERSCRIPT_MINUS || symbols::superscript_digit(c).is_some() => self.superscript(start),
+                ROOT => {
+                    self.bump();
+                    Some(self.token(TokenType::Root, start))
+                }
+                c if c.is_ascii_digit() => Some(self.number(start)),
+                '.' if self.peek_at(1).is_some_and(|c| c.is_ascii_digit()) => Some(self.number(start)),
+                c if c.is_alphabetic() => self.name(start),
+                '"' => self.text(start),
+                _ => self.symbol(start),
+            };
+            if token.is_some() {
+                return token;
             }
         }
     }
-
-    Line { tokens:tokens, number:line_number }
 }
 
-/// Parses a string into an i32 value.
-/// 
+/// Returns the value of a number literal: decimal with an optional exponent, hexadecimal (`0x`) or binary (`0b`),
+/// with `_` separating digits.
+///
 /// # Arguments
-/// 
-/// * `s` - A reference to the input string to be parsed.
-/// 
+/// * `text` - The literal as written in the source.
+///
 /// # Returns
-/// 
-/// * `Result<i32>` - An `Ok` variant containing the parsed i32 value if successful, or an `Err` variant with an `io::Error` if parsing fails.
-pub fn parse_str_to_i32(s: &str) -> Result<i32> {
-    match s.trim().parse::<i32>() {
-        Ok(num) => Ok(num),
-        Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+/// The value, or `None` if the literal is malformed.
+pub fn number_value(text: &str) -> Option<f64> {
+    let digits = text.replace('_', "");
+    if let Some(hex) = digits.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok().map(|x| x as f64)
+    }
+    else if let Some(binary) = digits.strip_prefix("0b") {
+        u64::from_str_radix(binary, 2).ok().map(|x| x as f64)
+    }
+    else {
+        digits.parse::<f64>().ok()
     }
 }
 
-/// Checks if the input string contains a single alphabetical character.
-/// 
-/// # Arguments
-/// 
-/// * `s` - A string slice to be checked.
-/// 
-/// # Returns
-/// 
-/// * `true` if the string contains a single alphabetical character, `false` otherwise.
-/// 
-/// # Panics
-/// 
-/// Panics if the input string is not a single character, otherwise returns `false`.
-pub fn is_alphabetical(s: &str) -> bool {
-    if s.is_empty() {
-        return false;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the types and values of the tokens of a source.
+    fn tokens(source: &str) -> Vec<(TokenType, String)> {
+        Lexer::new(source, 0).map(|x| (x.token_type, x.value)).collect()
     }
-    if s.len() == 1 {
-        match s.parse::<char>() {
-            Ok(c) => return c.is_alphabetic(),
-            Err(_) => return false
-        };
+
+    /// Returns the tokens of each statement, joined with spaces.
+    fn statements(source: &str) -> Vec<String> {
+        let (lines, errors) = get_lexer_lines(source, 0);
+        assert!(errors.is_empty(), "{:?}", errors);
+        lines.iter().map(|x| x.tokens.iter().map(|x| x.value.as_str()).collect::<Vec<_>>().join(" ")).collect()
     }
-    else {
-        return false;
+
+    #[test]
+    fn number_literals() {
+        for (text, value) in [("0xFF", 255.0), ("0b1010", 10.0), ("1_000_000", 1e6), ("0x_1", 0.0), ("1.5e3", 1500.0), ("2e-2", 0.02), (".5", 0.5)] {
+            let scanned = tokens(text);
+            assert_eq!(scanned[0].0, TokenType::Number, "{}", text);
+            assert_eq!(number_value(&scanned[0].1), Some(value), "{}", text);
+        }
+        // a separator must sit between two digits, and "e" without digits is the constant
+        assert_eq!(tokens("1_"), [(TokenType::Number, "1".to_string()), (TokenType::Underscore, "_".to_string())]);
+        assert_eq!(tokens("2e"), [(TokenType::Number, "2".to_string()), (TokenType::Lowercase, "e".to_string())]);
+        assert_eq!(tokens("0b2"), [(TokenType::Number, "0".to_string()), (TokenType::Lowercase, "b".to_string()), (TokenType::Number, "2".to_string())]);
+        assert_eq!(number_value("0xFFFFFFFFFFFFFFFFF"), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn lines_continue_within_brackets_and_after_a_backslash() {
+        assert_eq!(statements("{1, 2,\n 3} -> W\nf(1,\n2)"), ["{ 1 , 2 , 3 } -> W", "f ( 1 , 2 )"]);
+        assert_eq!(statements("1 +\\\n 2\n3"), ["1 + 2", "3"]);
+        // an open bracket stops continuing at a blank line and where a new statement starts
+        assert_eq!(statements("f(1,\n\n2)"), ["f ( 1 ,", "2 )"]);
+        assert_eq!(statements("f(1\nX -> Y"), ["f ( 1", "X -> Y"]);
+        assert_eq!(statements("f(1\n$1"), ["f ( 1", "$ 1"]);
+        let (_, errors) = get_lexer_lines("1 \\ 2", 0);
+        assert_eq!(errors[0].message, "Expected '\\' to be at the end of line, continuing it onto the next");
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(statements("1 /* one */ + 2 ... to the end\n/* two\nlines */\n3"), ["1 + 2", "3"]);
+        let (lines, _) = get_lexer_lines("/**\n * Doubles.\n * Twice.\n */\nf(X) => 2X", 0);
+        assert_eq!(lines[0].tokens[0].token_type, TokenType::Doc);
+        assert_eq!(lines[0].tokens[0].value, "Doubles.\nTwice.");
+        assert_eq!(lines[0].number, 5);
+        // inside a statement a doc comment is only a comment
+        assert_eq!(statements("f(1, /** not a doc */ 2)"), ["f ( 1 , 2 )"]);
+        let (_, errors) = get_lexer_lines("1 /* open", 0);
+        assert_eq!(errors[0].message, "Unterminated comment, expected closing '*/'");
+    }
+
+    #[test]
+    fn spans_count_characters_in_columns_and_bytes_in_offsets() {
+        let spans: Vec<(String, Span)> = Lexer::new("π × √X\n  \"é\" ÷ ab", 3).map(|x| (x.value, x.span)).collect();
+        let position = |value: &str| spans.iter().find(|x| x.0 == value).unwrap().1;
+        assert_eq!(position("X"), Span { offset: 9, length: 1, line: 1, column: 6, file: 3 });
+        assert_eq!(position("é"), Span { offset: 13, length: 4, line: 2, column: 3, file: 3 });
+        assert_eq!(position("ab"), Span { offset: 21, length: 2, line: 2, column: 9, file: 3 });
+        // a symbol standing for ASCII spans the whole symbol
+        assert_eq!(position("*"), Span { offset: 3, length: 2, line: 1, column: 3, file: 3 });
+        let (_, errors) = get_lexer_lines("1 + ¤", 0);
+        assert_eq!(errors[0].span, Some(Span { offset: 4, length: 2, line: 1, column: 5, file: 0 }));
+    }
+}
@@ -0,0 +1,18 @@
+//! Calculator Language, a language for writing mathematical expressions.
+//!
+//! A host program lexes and parses a script, then runs it with an `engine::Engine`,
+//! registering its own functions with `Engine::register_fn`.
+pub mod diagnostic;
+pub mod lexer;
+pub mod parser;
+pub mod interpreter;
+pub mod engine;
+pub mod io;
+pub mod format;
+pub mod numeric;
+pub mod random;
+pub mod stats;
+pub mod symbols;
+pub mod import;
+pub mod limits;
+pub mod prelude;
@@ -0,0 +1,154 @@
+use crate::diagnostic::{raise, Diagnostic};
+use crate::interpreter::Value;
+use crate::lexer::Span;
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+/// Stack of a spawned thread, which the default limits fit.
+const THREAD_STACK_SIZE: usize = 2 << 20;
+/// Stack used by a call of a defined function, or by a bracket parsed and solved, in a debug build with room to spare.
+/// Each uses up to about 22 KiB.
+const STACK_PER_LEVEL: usize = 24 << 10;
+/// Steps between checks of the time limit.
+const STEPS_PER_CLOCK_CHECK: u64 = 1024;
+
+/// Limits on running a script, for scripts that can not be trusted, set with `Engine::set_limits`. `None` is no limit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    /// Expressions evaluated.
+    pub steps: Option<u64>,
+    /// Calls of defined functions inside each other. Each call uses stack, so a deeper limit needs a thread with
+    /// a larger stack, like the one `calc_lang` runs scripts on.
+    pub depth: Option<usize>,
+    /// Brackets, calls and operands of prefix and right-associative operators inside each other, checked by
+    /// `parser::parse_with`. Each uses stack like a call.
+    pub nesting: Option<usize>,
+    /// Elements of any one set.
+    pub set_size: Option<usize>,
+    /// Time spent running.
+    pub time: Option<Duration>
+}
+
+impl Default for Limits {
+    /// The limits of `for_stack` for the 2 MiB stack of a spawned thread.
+    fn default() -> Self {
+        Limits::for_stack(THREAD_STACK_SIZE)
+    }
+}
+
+impl Limits {
+    /// No limits at all, which the prelude runs with.
+    pub fn none() -> Self {
+        Limits { steps: None, depth: None, nesting: None, set_size: None, time: None }
+    }
+
+    /// Limits the call depth and nesting of a script run on a thread with `stack_size` bytes of stack, so it
+    /// stops with an error instead of overflowing the stack. Calls are counted apart from nesting, so a script
+    /// going near both limits at once can still overflow.
+    pub fn for_stack(stack_size: usize) -> Self {
+        let levels = stack_size / STACK_PER_LEVEL;
+        Limits { depth: Some(levels), nesting: Some(levels), ..Limits::none() }
+    }
+}
+
+/// Counts one run of a script towards its limits, each run of `Engine::run` having its own.
+pub struct Counter {
+    limits: Limits,
+    /// When the run runs out of time.
+    deadline: Option<Instant>,
+    /// Expressions evaluated and defined functions being called.
+    steps: Cell<u64>,
+    depth: Cell<usize>
+}
+
+impl Counter {
+    /// Starts counting towards the limits of a run about to start.
+    pub fn new(limits: Limits) -> Self {
+        Counter { limits, deadline: limits.time.map(|x| Instant::now() + x), steps: Cell::new(0), depth: Cell::new(0) }
+    }
+
+    /// The limits being counted towards.
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Counts an expression evaluated, raising an error once the steps or time run out.
+    pub fn step(&self, span: Span) {
+        let steps = self.steps.get() + 1;
+        self.steps.set(steps);
+        if let Some(max) = self.limits.steps.filter(|x| steps > *x) {
+            let hint = "the script may loop forever, or the limit can be raised with `Limits::steps`";
+            raise(Diagnostic::new(format!("Step limit of {} reached", max)).at(span).with_code("step-limit").with_hint(Some(hint.to_string())));
+        }
+        if steps.is_multiple_of(STEPS_PER_CLOCK_CHECK) && self.deadline.is_some_and(|x| Instant::now() > x) {
+            let time = self.limits.time.unwrap_or_default();
+            let hint = "the limit can be raised with `Limits::time`";
+            raise(Diagnostic::new(format!("Time limit of {}s reached", time.as_secs_f64())).at(span).with_code("time-limit").with_hint(Some(hint.to_string())));
+        }
+    }
+
+    /// Counts a call of a defined function starting, raising an error at the call once calls are nested too deeply.
+    pub fn enter(&self) {
+        let depth = self.depth.get() + 1;
+        self.depth.set(depth);
+        if let Some(max) = self.limits.depth.filter(|x| depth > *x) {
+            let hint = "the function may call itself forever, or the limit can be raised with `Limits::depth`";
+            raise(Diagnostic::new(format!("Call depth limit of {} reached", max)).with_code("depth-limit").with_hint(Some(hint.to_string())));
+        }
+    }
+
+    /// Counts a call of a defined function returning.
+    pub fn leave(&self) {
+        self.depth.set(self.depth.get() - 1);
+    }
+
+    /// Raises an error if a value is a set with more elements than allowed.
+    pub fn check_set(&self, value: &Value, span: Span) {
+        if let (Value::Set(set), Some(max)) = (value, self.limits.set_size) {
+            if set.len() > max {
+                let hint = "the limit can be raised with `Limits::set_size`";
+                raise(Diagnostic::new(format!("Set of {} elements is over the limit of {}", set.len(), max)).at(span).with_code("set-size-limit").with_hint(Some(hint.to_string())));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::run_script;
+
+    fn code(script: &str, limits: Limits) -> Option<&'static str> {
+        run_script(script, "", limits).unwrap_err()[0].code
+    }
+
+    #[test]
+    fn scripts_stop_at_their_limits() {
+        let steps = Limits { steps: Some(100), ..Limits::default() };
+        assert_eq!(code("$0\ngoto(0)", steps), Some("step-limit"));
+        assert_eq!(code("f(X) => f(X)\nf(1)", Limits::default()), Some("depth-limit"));
+        let set_size = Limits { set_size: Some(2), ..Limits::default() };
+        assert_eq!(code("displayln({1, 2}), displayln({1, 2, 3})", set_size), Some("set-size-limit"));
+        let time = Limits { time: Some(Duration::ZERO), ..Limits::default() };
+        assert_eq!(code("$0\ngoto(0)", time), Some("time-limit"));
+        // numeric functions count the calls of the function they are given
+        assert_eq!(code("f(X) => 1\ndisplayln(summation(f, 0, 1e15))", time), Some("time-limit"));
+    }
+
+    #[test]
+    fn limit_hints_name_the_field() {
+        let errors = run_script("$0\ngoto(0)", "", Limits { steps: Some(10), ..Limits::default() }).unwrap_err();
+        assert!(errors[0].hint.as_ref().is_some_and(|x| x.contains("`Limits::steps`")));
+    }
+
+    #[test]
+    fn each_run_counts_its_own_limits() {
+        // the prelude runs without limits, and a run stopped by one leaves the next untouched
+        assert_eq!(code("displayln(pi)", Limits { steps: Some(1), ..Limits::default() }), Some("step-limit"));
+        assert_eq!(run_script("displayln(pi)", "", Limits::default()), Ok("3.141592653589793\n".to_string()));
+        let strict = std::thread::spawn(|| code("$0\ngoto(0)", Limits { steps: Some(1000), ..Limits::default() }));
+        let loose = std::thread::spawn(|| run_script("$0\nI + 1 -> I, I < 2000: goto(0)\ndisplayln(I)", "", Limits::none()));
+        assert_eq!(strict.join().unwrap(), Some("step-limit"));
+        assert_eq!(loose.join().unwrap(), Ok("2000\n".to_string()));
+    }
+}
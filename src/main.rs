@@ -1,22 +1,123 @@
-use std::io::Result;
 use std::fs;
 use std::env;
-mod lexer;
-mod parser;
-mod interpreter;
+use std::io::IsTerminal;
+use std::panic;
+use calc_lang::{diagnostic, interpreter, lexer, parser, symbols};
+use calc_lang::diagnostic::ErrorFormat;
+use calc_lang::engine::Engine;
+use calc_lang::limits::Limits;
+use calc_lang::symbols::Notation;
+
+/// Command line options, `calc_lang [--seed N] [--precision N] [--error-format color|plain|json] [--convert ascii|unicode] [--docs] [--path DIR]... [--no-prelude]
+/// [--max-steps N] [--max-depth N] [--max-nesting N] [--max-set-size N] [--timeout SECONDS] [file]`.
+struct Options {
+    file_path: String,
+    seed: Option<u64>,
+    precision: Option<usize>,
+    error_format: ErrorFormat,
+    /// Print the file with ASCII or Unicode symbols instead of running it.
+    convert: Option<Notation>,
+    /// Print the documentation of the functions the file defines instead of running it.
+    docs: bool,
+    /// Directories searched for imported files, before the ones in `CALC_PATH`.
+    search_paths: Vec<String>,
+    /// Run the file without the constants and functions of the prelude.
+    no_prelude: bool,
+    limits: Limits
+}
+
+/// Stack of the thread scripts run on, which the default call depth and nesting limits are set from.
+const STACK_SIZE: usize = 1 << 30;
 
 fn main() {
-    // get contents -- get_file_from_args().unwrap();
-    let contents = fs::read_to_string("src/calculation.txt").unwrap();
+    let options = get_options_from_args();
+    // get contents
+    let contents = fs::read_to_string(&options.file_path).unwrap_or_else(|e| {
+        eprintln!("Could not read '{}': {}", options.file_path, e);
+        std::process::exit(1);
+    });
+    let file = diagnostic::add_file(&options.file_path, &contents);
+
+    // errors are rendered below instead of the default panic message
+    panic::set_hook(Box::new(|_| {}));
+    let run = || {
+        if let Some(notation) = options.convert {
+            print!("{}", symbols::convert(&contents, notation));
+            return vec![];
+        }
+
+        // lexing
+        let (lexer_lines, mut diagnostics) = lexer::get_lexer_lines(&contents, file);
+
+        // parsing
+        let (parser_lines, parser_diagnostics) = parser::parse_with(lexer_lines, &options.limits);
+        diagnostics.extend(parser_diagnostics);
+        diagnostics.sort_by_key(|x| x.span.map(|x| x.offset));
+        if !diagnostics.is_empty() {
+            return diagnostics;
+        }
+        if options.docs {
+            print_docs(&parser_lines);
+            return vec![];
+        }
+        
+        // interpreter
+        let mut engine = Engine::new();
+        engine.set_limits(options.limits);
+        engine.set_prelude(!options.no_prelude);
+        if let Some(seed) = options.seed {
+            engine.seed(seed);
+        }
+        if let Some(precision) = options.precision {
+            engine.set_precision(precision);
+        }
+        for path in &options.search_paths {
+            engine.add_search_path(path);
+        }
+        if let Some(paths) = env::var_os("CALC_PATH") {
+            env::split_paths(&paths).for_each(|x| engine.add_search_path(x));
+        }
+        engine.run(parser_lines).err().unwrap_or_default()
+    };
+    // deeply nested calls need more stack than the main thread has
+    let mut diagnostics = std::thread::scope(|scope| {
+        let thread = std::thread::Builder::new().stack_size(STACK_SIZE);
+        thread.spawn_scoped(scope, || panic::catch_unwind(run).unwrap_or_else(diagnostic::from_panic)).unwrap().join().unwrap()
+    });
+
+    for diagnostic in &mut diagnostics {
+        if let Some(hint) = diagnostic.code.and_then(limit_hint) {
+            diagnostic.hint = Some(hint.to_string());
+        }
+    }
+    if !diagnostics.is_empty() {
+        eprintln!("{}", diagnostic::render_all(&diagnostics, &options.file_path, options.error_format));
+        std::process::exit(1);
+    }
+}
 
-    // lexing
-    let lexer_lines = lexer::get_lexer_lines(&contents);
+/// Returns the hint for an error of a limit, naming the command line option raising it instead of the `Limits` field.
+fn limit_hint(code: &str) -> Option<&'static str> {
+    match code {
+        "step-limit" => Some("the script may loop forever, or the limit can be raised with --max-steps"),
+        "depth-limit" => Some("the function may call itself forever, or the limit can be raised with --max-depth"),
+        "nesting-limit" => Some("the limit can be raised with --max-nesting"),
+        "set-size-limit" => Some("the limit can be raised with --max-set-size"),
+        "time-limit" => Some("the limit can be raised with --timeout"),
+        _ => None,
+    }
+}
 
-    // parsing
-    let parser_lines = parser::parse(lexer_lines);
-    
-    // interpreter
-    interpreter::interpret(parser_lines);
+/// Prints the signature and doc comment of every function defined in the parsed lines.
+fn print_docs(parser_lines: &[Option<parser::Expr>]) {
+    let functions: Vec<String> = parser_lines.iter().flatten().filter_map(|l| match l {
+        parser::Expr::FuncDef { name, parameters, body, doc, .. } => {
+            let function = interpreter::DefinedFunction::new(name.clone(), Some(parameters.clone()), Some(body.clone()), doc.clone());
+            Some(function.help())
+        }
+        _ => None,
+    }).collect();
+    println!("{}", functions.join("\n\n"));
 }
 
 #[allow(dead_code)]
@@ -25,13 +126,13 @@ fn debug_lexer(lexer_lines: Vec<lexer::Line>) {
     for l in &lexer_lines {
         println!("  {}:", &l.number);
         for t in &l.tokens {
-            println!("    {}: {}", t.token_type.to_string(), &t.value);
+            println!("    {}: {} at {}:{}", t.token_type, &t.value, t.span.line, t.span.column);
         }
     }
 }
 
 #[allow(dead_code)]
-fn debug_parser(parser_lines: Vec<Option<parser::ExprNode>>) {
+fn debug_parser(parser_lines: Vec<Option<parser::Expr>>) {
     println!("PARSING:");
     for l in &parser_lines {
         if l.is_some() {
@@ -40,15 +141,96 @@ fn debug_parser(parser_lines: Vec<Option<parser::ExprNode>>) {
     }
 }
 
-fn get_file_from_args() -> Result<String> {
-    let args: Vec<String> = env::args().collect();
-    
-    if args.len() != 2 {
-        eprintln!("Expects 1 argument being the file path");
-        std::process::exit(1);
-    } else {
-        let file_path = &args[1];
-        let content = fs::read_to_string(file_path)?;
-        Ok(content)
+/// Reads the options from the command line arguments, exiting on invalid arguments.
+fn get_options_from_args() -> Options {
+    let color = std::io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none();
+    let error_format = if color { ErrorFormat::Color } else { ErrorFormat::Plain };
+    let mut options = Options { file_path: "src/calculation.txt".to_string(), seed: None, precision: None, error_format, convert: None, docs: false, search_paths: vec![], no_prelude: false, limits: Limits::for_stack(STACK_SIZE) };
+    let mut args = env::args().skip(1);
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => {
+                let value = args.next().unwrap_or_default();
+                match value.parse::<u64>() {
+                    Ok(seed) => options.seed = Some(seed),
+                    Err(_) => {
+                        eprintln!("Expects '--seed' to be followed by a whole number, got '{}'", value);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--precision" => {
+                let value = args.next().unwrap_or_default();
+                match value.parse::<usize>() {
+                    Ok(precision) => options.precision = Some(precision),
+                    Err(_) => {
+                        eprintln!("Expects '--precision' to be followed by a whole number, got '{}'", value);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--error-format" => {
+                let value = args.next().unwrap_or_default();
+                options.error_format = match value.as_str() {
+                    "color" => ErrorFormat::Color,
+                    "plain" => ErrorFormat::Plain,
+                    "json" => ErrorFormat::Json,
+                    _ => {
+                        eprintln!("Expects '--error-format' to be followed by 'color', 'plain' or 'json', got '{}'", value);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--convert" => {
+                let value = args.next().unwrap_or_default();
+                options.convert = match value.as_str() {
+                    "ascii" => Some(Notation::Ascii),
+                    "unicode" => Some(Notation::Unicode),
+                    _ => {
+                        eprintln!("Expects '--convert' to be followed by 'ascii' or 'unicode', got '{}'", value);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--docs" => options.docs = true,
+            "--no-prelude" => options.no_prelude = true,
+            "--max-steps" => options.limits.steps = Some(limit(args.next(), &arg)),
+            "--max-depth" => options.limits.depth = Some(limit(args.next(), &arg)),
+            "--max-nesting" => options.limits.nesting = Some(limit(args.next(), &arg)),
+            "--max-set-size" => options.limits.set_size = Some(limit(args.next(), &arg)),
+            "--timeout" => {
+                let value = args.next().unwrap_or_default();
+                match value.parse::<f64>().ok().and_then(|x| std::time::Duration::try_from_secs_f64(x).ok()) {
+                    Some(time) => options.limits.time = Some(time),
+                    None => {
+                        eprintln!("Expects '--timeout' to be followed by a number of seconds, got '{}'", value);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            "--path" => match args.next() {
+                Some(path) => options.search_paths.push(path),
+                None => {
+                    eprintln!("Expects '--path' to be followed by a directory");
+                    std::process::exit(1);
+                }
+            },
+            _ if arg.starts_with("--") => {
+                eprintln!("Unknown option '{}'", arg);
+                std::process::exit(1);
+            }
+            _ => options.file_path = arg,
+        }
     }
-}
\ No newline at end of file
+    options
+}
+
+/// Reads the whole number following a limit option, exiting if there is none.
+fn limit<T: std::str::FromStr>(value: Option<String>, option: &str) -> T {
+    let value = value.unwrap_or_default();
+    value.parse::<T>().unwrap_or_else(|_| {
+        eprintln!("Expects '{}' to be followed by a whole number, got '{}'", option, value);
+        std::process::exit(1);
+    })
+}
@@ -0,0 +1,902 @@
+use crate::interpreter::Value;
+use std::cell::Cell;
+use std::collections::HashMap;
+
+/// A user-defined function passed by name to a numeric built-in, called with numeric arguments.
+pub type Callback<'a> = Box<dyn Fn(&[f64]) -> f64 + 'a>;
+
+/// A built-in taking `functions` leading function names (-1 for one or more) followed by any of `parameters` values,
+/// and the error estimate of the run, which it sets for `error()` to return.
+pub struct NumericFunction {
+    pub name: String,
+    pub functions: i32,
+    pub parameters: Vec<i32>,
+    pub execute: fn(&[Callback], Vec<Value>, &Cell<f64>) -> Value
+}
+/// The numeric functions a session can call, by name.
+pub type NumericFunctions = HashMap<String, NumericFunction>;
+
+const DEFAULT_TOLERANCE: f64 = 1e-10;
+const MAX_INTERVALS: usize = 2000;
+
+// 15-point Kronrod nodes and weights with the embedded 7-point Gauss weights
+const KRONROD_NODES: [f64; 8] = [
+    0.991_455_371_120_812_6, 0.949_107_912_342_758_5, 0.864_864_423_359_769_1, 0.741_531_185_599_394_4,
+    0.586_087_235_467_691_1, 0.405_845_151_377_397_2, 0.207_784_955_007_898_47, 0.0
+];
+const KRONROD_WEIGHTS: [f64; 8] = [
+    0.022_935_322_010_529_225, 0.063_092_092_629_978_55, 0.104_790_010_322_250_18, 0.140_653_259_715_525_92,
+    0.169_004_726_639_267_9, 0.190_350_578_064_785_4, 0.204_432_940_075_298_9, 0.209_482_141_084_727_83
+];
+const GAUSS_WEIGHTS: [f64; 4] = [
+    0.129_484_966_168_869_7, 0.279_705_391_489_276_7, 0.381_830_050_505_118_9, 0.417_959_183_673_469_4
+];
+
+/// Returns a vector of numeric functions, built-ins whose leading arguments are names of defined functions.
+pub fn get_numeric_functions() -> Vec<NumericFunction> {
+    vec![
+        NumericFunction {
+            name: "integrate".to_string(),
+            functions: 1,
+            parameters: vec![2, 3],
+            execute: |f, v, last_error| {
+                let (value, error) = integrate(&|x| f[0](&[x]), v[0].number(), v[1].number(), tolerance(&v, 2));
+                Value::Number(record_error(last_error, value, error))
+            }
+        },
+        NumericFunction {
+            name: "nderiv".to_string(),
+            functions: 1,
+            parameters: vec![1, 2],
+            execute: |f, v, last_error| {
+                let (value, error) = derivative(&|x| f[0](&[x]), v[0].number(), tolerance(&v, 1));
+                Value::Number(record_error(last_error, value, error))
+            }
+        },
+        NumericFunction {
+            name: "limit".to_string(),
+            functions: 1,
+            parameters: vec![1, 2],
+            execute: |f, v, last_error| {
+                let direction = v.get(1).map_or(0.0, |d| d.number());
+                let (value, error) = limit(&|x| f[0](&[x]), v[0].number(), direction);
+                Value::Number(record_error(last_error, value, error))
+            }
+        },
+        NumericFunction {
+            name: "summation".to_string(),
+            functions: 1,
+            parameters: vec![2],
+            execute: |f, v, last_error| {
+                let mut result = 0.0;
+                for k in integer_range(v[0].number(), v[1].number()) {
+                    result += f[0](&[k]);
+                }
+                Value::Number(record_error(last_error, result, 0.0))
+            }
+        },
+        NumericFunction {
+            name: "product".to_string(),
+            functions: 1,
+            parameters: vec![2],
+            execute: |f, v, last_error| {
+                let mut result = 1.0;
+                for k in integer_range(v[0].number(), v[1].number()) {
+                    result *= f[0](&[k]);
+                }
+                Value::Number(record_error(last_error, result, 0.0))
+            }
+        },
+        NumericFunction {
+            name: "solve".to_string(),
+            functions: 1,
+            parameters: vec![1, 2],
+            execute: |f, v, last_error| {
+                let g = |x| f[0](&[x]);
+                let (value, error) = match v.len() {
+                    1 => newton(&g, v[0].number(), DEFAULT_TOLERANCE)
+                        .or_else(|| bracket(&g, v[0].number()).and_then(|(a, b)| brent(&g, a, b, DEFAULT_TOLERANCE)))
+                        .unwrap_or_else(|| panic!("No root found near {} in function: solve", v[0].number())),
+                    _ => brent(&g, v[0].number(), v[1].number(), DEFAULT_TOLERANCE)
+                        .unwrap_or_else(|| panic!("Expected the function to change sign between {} and {} in function: solve", v[0].number(), v[1].number())),
+                };
+                Value::Number(record_error(last_error, value, error))
+            }
+        },
+        NumericFunction {
+            name: "roots".to_string(),
+            functions: 0,
+            parameters: vec![1],
+            execute: |_, v, _| {
+                let roots = polynomial_roots(&coefficients(&v[0], "roots"));
+                let mut real: Vec<f64> = roots.iter().filter(|x| x.im.abs() <= 1e-6 * x.re.abs().max(1.0)).map(|x| x.re).collect();
+                real.sort_by(|x, y| x.total_cmp(y));
+                Value::Set(real)
+            }
+        },
+        NumericFunction {
+            name: "croots".to_string(),
+            functions: 0,
+            parameters: vec![1],
+            execute: |_, v, _| {
+                let roots = polynomial_roots(&coefficients(&v[0], "croots"));
+                Value::Set(roots.iter().flat_map(|x| [x.re, x.im]).collect())
+            }
+        },
+        NumericFunction {
+            name: "fsolve".to_string(),
+            functions: -1,
+            parameters: vec![1],
+            execute: |f, v, last_error| {
+                let guess = v[0].as_set().cloned().unwrap_or_else(|| vec![v[0].number()]);
+                if guess.len() != f.len() {
+                    panic!("Expected {} values in the initial guess, got {} in function: fsolve", f.len(), guess.len());
+                }
+                let (value, error) = newton_system(f, guess, DEFAULT_TOLERANCE)
+                    .unwrap_or_else(|| panic!("No solution found near {} in function: fsolve", v[0]));
+                record_error(last_error, 0.0, error);
+                Value::Set(value)
+            }
+        },
+        NumericFunction {
+            name: "minimize".to_string(),
+            functions: 1,
+            parameters: vec![1, 2],
+            execute: |f, v, last_error| {
+                Value::Set(optimize(&f[0], v, 1.0, last_error))
+            }
+        },
+        NumericFunction {
+            name: "maximize".to_string(),
+            functions: 1,
+            parameters: vec![1, 2],
+            execute: |f, v, last_error| {
+                Value::Set(optimize(&f[0], v, -1.0, last_error))
+            }
+        },
+        NumericFunction {
+            name: "error".to_string(),
+            functions: 0,
+            parameters: vec![0],
+            execute: |_, _, last_error| {
+                Value::Number(last_error.get())
+            }
+        }
+    ]
+}
+
+/// Stores the error estimate of a numeric built-in so that `error()` can return it.
+///
+/// # Arguments
+/// * `last_error` - The error estimate of the run.
+/// * `value` - The result of the computation.
+/// * `error` - The absolute error estimate of `value`.
+///
+/// # Returns
+/// The unchanged `value`.
+fn record_error(last_error: &Cell<f64>, value: f64, error: f64) -> f64 {
+    last_error.set(error);
+    value
+}
+
+/// Reads the optional tolerance argument at `index`, falling back to the default tolerance.
+fn tolerance(values: &[Value], index: usize) -> f64 {
+    match values.get(index) {
+        Some(v) if v.number() > 0.0 => v.number(),
+        Some(v) => panic!("Expected tolerance to be positive, got {}", v),
+        None => DEFAULT_TOLERANCE,
+    }
+}
+
+/// Returns the integers from `a` to `b` inclusive, both rounded towards the inside of the range.
+fn integer_range(a: f64, b: f64) -> impl Iterator<Item = f64> {
+    let start = a.ceil() as i64;
+    let end = b.floor() as i64;
+    (start..=end).map(|k| k as f64)
+}
+
+/// Integrates a function over an interval using adaptive Gauss-Kronrod quadrature.
+///
+/// # Arguments
+/// * `f` - The function to integrate.
+/// * `a` - The lower bound, may be infinite.
+/// * `b` - The upper bound, may be infinite.
+/// * `tol` - The requested absolute and relative tolerance.
+///
+/// # Returns
+/// The integral and an estimate of its absolute error.
+pub fn integrate(f: &dyn Fn(f64) -> f64, a: f64, b: f64, tol: f64) -> (f64, f64) {
+    if a == b {
+        return (0.0, 0.0);
+    }
+    if a > b {
+        let (value, error) = integrate(f, b, a, tol);
+        return (-value, error);
+    }
+    if a.is_infinite() && b.is_infinite() {
+        // split at zero so each half has one infinite bound
+        let (left, left_error) = integrate(f, a, 0.0, tol / 2.0);
+        let (right, right_error) = integrate(f, 0.0, b, tol / 2.0);
+        return (left + right, left_error + right_error);
+    }
+    if b.is_infinite() {
+        // x = a + t / (1 - t) maps [0, 1) onto [a, inf)
+        let g = |t: f64| f(a + t / (1.0 - t)) / ((1.0 - t) * (1.0 - t));
+        return adaptive_kronrod(&g, 0.0, 1.0, tol);
+    }
+    if a.is_infinite() {
+        // x = b - (1 - t) / t maps (0, 1] onto (-inf, b]
+        let g = |t: f64| f(b - (1.0 - t) / t) / (t * t);
+        return adaptive_kronrod(&g, 0.0, 1.0, tol);
+    }
+    adaptive_kronrod(f, a, b, tol)
+}
+
+/// Repeatedly bisects the subinterval with the largest Gauss-Kronrod error estimate until the total meets the tolerance.
+fn adaptive_kronrod(f: &dyn Fn(f64) -> f64, a: f64, b: f64, tol: f64) -> (f64, f64) {
+    let (value, error) = kronrod(f, a, b);
+    let mut intervals = vec![(a, b, value, error)];
+    let mut total = value;
+    let mut total_error = error;
+
+    while intervals.len() < MAX_INTERVALS && total_error > tol.max(tol * total.abs()) && total.is_finite() {
+        let worst = intervals.iter().enumerate().max_by(|x, y| x.1.3.total_cmp(&y.1.3)).unwrap().0;
+        let (a, b, value, error) = intervals.swap_remove(worst);
+        let middle = (a + b) / 2.0;
+        let (left, left_error) = kronrod(f, a, middle);
+        let (right, right_error) = kronrod(f, middle, b);
+        total += left + right - value;
+        total_error += left_error + right_error - error;
+        intervals.push((a, middle, left, left_error));
+        intervals.push((middle, b, right, right_error));
+    }
+    // re-sum to drop the rounding accumulated by the running updates
+    let total = intervals.iter().map(|x| x.2).sum();
+    let total_error = intervals.iter().map(|x| x.3).sum();
+    (total, total_error)
+}
+
+/// Applies the 15-point Kronrod rule to an interval.
+///
+/// # Returns
+/// The Kronrod estimate and its difference from the embedded 7-point Gauss estimate.
+fn kronrod(f: &dyn Fn(f64) -> f64, a: f64, b: f64) -> (f64, f64) {
+    let center = (a + b) / 2.0;
+    let half = (b - a) / 2.0;
+    let f_center = f(center);
+    let mut kronrod_sum = KRONROD_WEIGHTS[7] * f_center;
+    let mut gauss_sum = GAUSS_WEIGHTS[3] * f_center;
+    for i in 0..7 {
+        let dx = half * KRONROD_NODES[i];
+        let pair = f(center - dx) + f(center + dx);
+        kronrod_sum += KRONROD_WEIGHTS[i] * pair;
+        if i % 2 == 1 {
+            gauss_sum += GAUSS_WEIGHTS[i / 2] * pair;
+        }
+    }
+    (kronrod_sum * half, ((kronrod_sum - gauss_sum) * half).abs())
+}
+
+/// Differentiates a function at a point using Ridders' extrapolation of central differences.
+///
+/// # Arguments
+/// * `f` - The function to differentiate.
+/// * `x` - The point to differentiate at.
+/// * `tol` - The error at which extrapolation stops early.
+///
+/// # Returns
+/// The derivative and an estimate of its absolute error.
+pub fn derivative(f: &dyn Fn(f64) -> f64, x: f64, tol: f64) -> (f64, f64) {
+    const SIZE: usize = 10;
+    const SHRINK: f64 = 1.4;
+    let mut h = 0.1 * x.abs().max(1.0);
+    let mut table = [[0.0; SIZE]; SIZE];
+    table[0][0] = (f(x + h) - f(x - h)) / (2.0 * h);
+    let mut result = table[0][0];
+    let mut error = f64::INFINITY;
+
+    for i in 1..SIZE {
+        h /= SHRINK;
+        table[0][i] = (f(x + h) - f(x - h)) / (2.0 * h);
+        let mut factor = SHRINK * SHRINK;
+        for j in 1..=i {
+            table[j][i] = (table[j - 1][i] * factor - table[j - 1][i - 1]) / (factor - 1.0);
+            factor *= SHRINK * SHRINK;
+            let estimate = (table[j][i] - table[j - 1][i]).abs().max((table[j][i] - table[j - 1][i - 1]).abs());
+            if estimate <= error {
+                error = estimate;
+                result = table[j][i];
+            }
+        }
+        // stop once higher orders get worse
+        if (table[i][i] - table[i - 1][i - 1]).abs() >= 2.0 * error || error <= tol {
+            break;
+        }
+    }
+    (result, error)
+}
+
+/// Evaluates the limit of a function at a point by Richardson extrapolation of values approaching it.
+///
+/// # Arguments
+/// * `f` - The function to take the limit of.
+/// * `x` - The point being approached.
+/// * `direction` - Positive to approach from above, negative from below and zero from both sides.
+///
+/// # Returns
+/// The limit and an estimate of its absolute error, or NaN when the one-sided limits disagree.
+pub fn limit(f: &dyn Fn(f64) -> f64, x: f64, direction: f64) -> (f64, f64) {
+    if direction > 0.0 {
+        return one_sided_limit(f, x, 1.0);
+    }
+    if direction < 0.0 {
+        return one_sided_limit(f, x, -1.0);
+    }
+    let (above, above_error) = one_sided_limit(f, x, 1.0);
+    let (below, below_error) = one_sided_limit(f, x, -1.0);
+    let error = above_error.max(below_error);
+    if (above - below).abs() > (1e3 * error).max(1e-6 * above.abs().max(1.0)) {
+        return (f64::NAN, f64::INFINITY);
+    }
+    ((above + below) / 2.0, error + (above - below).abs() / 2.0)
+}
+
+/// Extrapolates `f(x + sign * h)` to `h = 0` from a halving sequence of steps.
+fn one_sided_limit(f: &dyn Fn(f64) -> f64, x: f64, sign: f64) -> (f64, f64) {
+    const SIZE: usize = 12;
+    let mut h = 0.125 * x.abs().max(1.0);
+    let mut table = [[0.0; SIZE]; SIZE];
+    let mut result = f(x + sign * h);
+    let mut error = f64::INFINITY;
+    table[0][0] = result;
+
+    for i in 1..SIZE {
+        h /= 2.0;
+        table[i][0] = f(x + sign * h);
+        let mut factor = 2.0;
+        for j in 1..=i {
+            table[i][j] = table[i][j - 1] + (table[i][j - 1] - table[i - 1][j - 1]) / (factor - 1.0);
+            factor *= 2.0;
+        }
+        let estimate = (table[i][i] - table[i - 1][i - 1]).abs();
+        if estimate <= error {
+            error = estimate;
+            result = table[i][i];
+        }
+        else if error.is_finite() {
+            break;
+        }
+    }
+    (result, error)
+}
+
+/// A complex number, used for polynomial roots.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+    fn abs(&self) -> f64 {
+        self.re.hypot(self.im)
+    }
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(self.re * other.re - self.im * other.im, self.re * other.im + self.im * other.re)
+    }
+    fn div(self, other: Complex) -> Complex {
+        let d = other.re * other.re + other.im * other.im;
+        Complex::new((self.re * other.re + self.im * other.im) / d, (self.im * other.re - self.re * other.im) / d)
+    }
+}
+
+/// Reads polynomial coefficients from a set, highest degree first, dropping leading zeros.
+fn coefficients(value: &Value, name: &str) -> Vec<f64> {
+    let set = value.as_set().cloned().unwrap_or_else(|| panic!("Expected a set of coefficients in function: {}", name));
+    set.into_iter().skip_while(|x| *x == 0.0).collect()
+}
+
+/// Finds a root of a function with Newton's method, using a central difference for the derivative.
+///
+/// # Arguments
+/// * `f` - The function to find a root of.
+/// * `guess` - The starting point.
+/// * `tol` - The relative step size at which the iteration has converged.
+///
+/// # Returns
+/// The root and the size of the last step, or `None` if the iteration diverged.
+pub fn newton(f: &dyn Fn(f64) -> f64, guess: f64, tol: f64) -> Option<(f64, f64)> {
+    let mut x = guess;
+    for _ in 0..100 {
+        let fx = f(x);
+        if fx == 0.0 {
+            return Some((x, 0.0));
+        }
+        let h = 1e-6 * x.abs().max(1.0);
+        let slope = (f(x + h) - f(x - h)) / (2.0 * h);
+        let step = fx / slope;
+        if !step.is_finite() {
+            return None;
+        }
+        x -= step;
+        if step.abs() <= tol * x.abs().max(1.0) {
+            return Some((x, step.abs()));
+        }
+    }
+    None
+}
+
+/// Searches outwards from a point for an interval over which a function changes sign.
+///
+/// # Returns
+/// The bounds of the interval, or `None` if no sign change was found.
+pub fn bracket(f: &dyn Fn(f64) -> f64, guess: f64) -> Option<(f64, f64)> {
+    let mut dx = 0.1 * guess.abs().max(1.0);
+    let f_guess = f(guess);
+    for _ in 0..60 {
+        for x in [guess - dx, guess + dx] {
+            if f(x) * f_guess <= 0.0 {
+                return Some((guess.min(x), guess.max(x)));
+            }
+        }
+        dx *= 2.0;
+    }
+    None
+}
+
+/// Finds a root of a function inside a bracketing interval with Brent's method.
+///
+/// # Arguments
+/// * `f` - The function to find a root of.
+/// * `a` - One bound of the interval.
+/// * `b` - The other bound of the interval.
+/// * `tol` - The relative width at which the interval has converged.
+///
+/// # Returns
+/// The root and the width of the final interval, or `None` if the function does not change sign.
+pub fn brent(f: &dyn Fn(f64) -> f64, a: f64, b: f64, tol: f64) -> Option<(f64, f64)> {
+    let (mut a, mut b) = (a, b);
+    let (mut fa, mut fb) = (f(a), f(b));
+    if fa * fb > 0.0 {
+        return None;
+    }
+    let (mut c, mut fc) = (a, fa);
+    let mut d = b - a;
+    let mut e = d;
+
+    for _ in 0..200 {
+        if fb * fc > 0.0 {
+            c = a;
+            fc = fa;
+            d = b - a;
+            e = d;
+        }
+        if fc.abs() < fb.abs() {
+            a = b;
+            b = c;
+            c = a;
+            fa = fb;
+            fb = fc;
+            fc = fa;
+        }
+        let limit = 2.0 * f64::EPSILON * b.abs() + 0.5 * tol * b.abs().max(1.0);
+        let middle = 0.5 * (c - b);
+        if middle.abs() <= limit || fb == 0.0 {
+            return Some((b, middle.abs() * 2.0));
+        }
+        if e.abs() >= limit && fa.abs() > fb.abs() {
+            // try inverse quadratic interpolation, or the secant method when only two points are distinct
+            let s = fb / fa;
+            let (mut p, mut q) = if a == c {
+                (2.0 * middle * s, 1.0 - s)
+            } else {
+                let q = fa / fc;
+                let r = fb / fc;
+                (s * (2.0 * middle * q * (q - r) - (b - a) * (r - 1.0)), (q - 1.0) * (r - 1.0) * (s - 1.0))
+            };
+            if p > 0.0 {
+                q = -q;
+            }
+            p = p.abs();
+            if 2.0 * p < (3.0 * middle * q - (limit * q).abs()).min((e * q).abs()) {
+                e = d;
+                d = p / q;
+            } else {
+                d = middle;
+                e = d;
+            }
+        } else {
+            d = middle;
+            e = d;
+        }
+        a = b;
+        fa = fb;
+        b += if d.abs() > limit { d } else { limit.copysign(middle) };
+        fb = f(b);
+    }
+    Some((b, (c - b).abs()))
+}
+
+/// Finds all complex roots of a polynomial with the Aberth-Ehrlich method.
+///
+/// # Arguments
+/// * `coefficients` - The coefficients, highest degree first.
+///
+/// # Returns
+/// A vector with one root per degree of the polynomial.
+pub fn polynomial_roots(coefficients: &[f64]) -> Vec<Complex> {
+    let degree = coefficients.len().saturating_sub(1);
+    if degree == 0 {
+        return vec![];
+    }
+    let monic: Vec<f64> = coefficients.iter().map(|x| x / coefficients[0]).collect();
+    let derivative: Vec<f64> = monic.iter().take(degree).enumerate().map(|(i, x)| x * (degree - i) as f64).collect();
+    let evaluate = |p: &[f64], z: Complex| p.iter().fold(Complex::new(0.0, 0.0), |acc, x| acc.mul(z).add(Complex::new(*x, 0.0)));
+
+    // start on a circle bounding every root, rotated off the real axis to avoid symmetric stalls
+    let radius = 1.0 + monic.iter().skip(1).map(|x| x.abs()).fold(0.0, f64::max);
+    let mut roots: Vec<Complex> = (0..degree).map(|k| {
+        let angle = 2.0 * std::f64::consts::PI * k as f64 / degree as f64 + 0.4;
+        Complex::new(radius * angle.cos(), radius * angle.sin())
+    }).collect();
+
+    for _ in 0..500 {
+        let mut largest_step: f64 = 0.0;
+        for i in 0..degree {
+            let value = evaluate(&monic, roots[i]);
+            if value.abs() == 0.0 {
+                continue;
+            }
+            let ratio = value.div(evaluate(&derivative, roots[i]));
+            let mut repulsion = Complex::new(0.0, 0.0);
+            for (j, other) in roots.iter().enumerate() {
+                if i != j {
+                    repulsion = repulsion.add(Complex::new(1.0, 0.0).div(roots[i].sub(*other)));
+                }
+            }
+            let step = ratio.div(Complex::new(1.0, 0.0).sub(ratio.mul(repulsion)));
+            if step.re.is_finite() && step.im.is_finite() {
+                roots[i] = roots[i].sub(step);
+                largest_step = largest_step.max(step.abs() / roots[i].abs().max(1.0));
+            }
+        }
+        if largest_step <= 1e-15 {
+            break;
+        }
+    }
+    roots
+}
+
+/// Solves a square system of equations with Newton's method, using forward differences for the Jacobian.
+///
+/// # Arguments
+/// * `functions` - The functions that should all be zero, each taking every unknown.
+/// * `guess` - The starting point.
+/// * `tol` - The relative step size at which the iteration has converged.
+///
+/// # Returns
+/// The solution and the size of the last step, or `None` if the iteration diverged.
+pub fn newton_system(functions: &[Callback], guess: Vec<f64>, tol: f64) -> Option<(Vec<f64>, f64)> {
+    let n = guess.len();
+    let mut x = guess;
+    for _ in 0..100 {
+        let fx: Vec<f64> = functions.iter().map(|f| f(&x)).collect();
+        if fx.iter().all(|v| *v == 0.0) {
+            return Some((x, 0.0));
+        }
+        let mut jacobian = vec![vec![0.0; n]; n];
+        for j in 0..n {
+            let h = 1e-7 * x[j].abs().max(1.0);
+            let mut shifted = x.clone();
+            shifted[j] += h;
+            for (i, f) in functions.iter().enumerate() {
+                jacobian[i][j] = (f(&shifted) - fx[i]) / h;
+            }
+        }
+        let step = solve_linear(jacobian, fx)?;
+        let mut size: f64 = 0.0;
+        for j in 0..n {
+            x[j] -= step[j];
+            size = size.max(step[j].abs() / x[j].abs().max(1.0));
+        }
+        if !size.is_finite() {
+            return None;
+        }
+        if size <= tol {
+            return Some((x, size));
+        }
+    }
+    None
+}
+
+/// Solves the linear system `matrix * x = vector` by Gaussian elimination with partial pivoting.
+///
+/// # Returns
+/// The solution, or `None` if the matrix is singular.
+fn solve_linear(mut matrix: Vec<Vec<f64>>, mut vector: Vec<f64>) -> Option<Vec<f64>> {
+    let n = vector.len();
+    for column in 0..n {
+        let pivot = (column..n).max_by(|x, y| matrix[*x][column].abs().total_cmp(&matrix[*y][column].abs()))?;
+        if matrix[pivot][column] == 0.0 {
+            return None;
+        }
+        matrix.swap(column, pivot);
+        vector.swap(column, pivot);
+        let pivot_row = matrix[column].clone();
+        for row in column + 1..n {
+            let factor = matrix[row][column] / pivot_row[column];
+            for (x, p) in matrix[row].iter_mut().zip(&pivot_row).skip(column) {
+                *x -= factor * p;
+            }
+            vector[row] -= factor * vector[column];
+        }
+    }
+    let mut solution = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| matrix[row][k] * solution[k]).sum();
+        solution[row] = (vector[row] - sum) / matrix[row][row];
+    }
+    Some(solution)
+}
+
+/// Minimizes `sign * f` from a guess or, with two values, over an interval.
+///
+/// # Arguments
+/// * `f` - The function to optimize.
+/// * `values` - Either a single guess, a set guess for functions of several parameters, or the bounds of an interval.
+/// * `sign` - 1 to minimize and -1 to maximize.
+/// * `last_error` - The error estimate of the run, set to that of the optimal value.
+///
+/// # Returns
+/// The optimal parameters followed by the optimal value.
+fn optimize(f: &Callback, values: Vec<Value>, sign: f64, last_error: &Cell<f64>) -> Vec<f64> {
+    let (mut point, value, error) = if values.len() == 2 {
+        let (x, fx, error) = golden_section(&|x| sign * f(&[x]), values[0].number(), values[1].number(), DEFAULT_TOLERANCE);
+        (vec![x], fx, error)
+    }
+    else {
+        let guess = values[0].as_set().cloned().unwrap_or_else(|| vec![values[0].number()]);
+        if guess.len() == 1 {
+            let g = |x| sign * f(&[x]);
+            let (name, direction) = if sign > 0.0 { ("minimize", "below") } else { ("maximize", "above") };
+            let (a, b) = bracket_minimum(&g, guess[0], name, direction);
+            let (x, fx, error) = golden_section(&g, a, b, DEFAULT_TOLERANCE);
+            (vec![x], fx, error)
+        }
+        else {
+            nelder_mead(&|x: &[f64]| sign * f(x), guess, DEFAULT_TOLERANCE)
+        }
+    };
+    record_error(last_error, 0.0, error);
+    point.push(sign * value);
+    point
+}
+
+/// Walks downhill from a point with growing steps until a minimum is enclosed.
+///
+/// # Arguments
+/// * `f` - The function to minimize.
+/// * `guess` - The point to start from.
+/// * `name` - The name of the calling function, for error messages.
+/// * `direction` - Which way the caller's own function is unbounded when `f` is unbounded below, "below" or "above".
+///
+/// # Returns
+/// The bounds of an interval containing a local minimum.
+pub fn bracket_minimum(f: &dyn Fn(f64) -> f64, guess: f64, name: &str, direction: &str) -> (f64, f64) {
+    const GROWTH: f64 = 1.618_033_988_749_895;
+    let mut step = 0.1 * guess.abs().max(1.0);
+    let (mut a, mut b) = (guess, guess + step);
+    let (mut fa, mut fb) = (f(a), f(b));
+    if fb > fa {
+        std::mem::swap(&mut a, &mut b);
+        std::mem::swap(&mut fa, &mut fb);
+        step = -step;
+    }
+    for _ in 0..100 {
+        step *= GROWTH;
+        let c = b + step;
+        let fc = f(c);
+        if fc >= fb {
+            return (a.min(c), a.max(c));
+        }
+        a = b;
+        b = c;
+        fb = fc;
+    }
+    panic!("Function is unbounded {} near {} in function: {}", direction, guess, name);
+}
+
+/// Minimizes a function of one parameter over an interval with golden section search.
+///
+/// # Arguments
+/// * `f` - The function to minimize.
+/// * `a` - One bound of the interval.
+/// * `b` - The other bound of the interval.
+/// * `tol` - The relative width at which the interval has converged.
+///
+/// # Returns
+/// The argument of the minimum, the minimum and the width of the final interval.
+pub fn golden_section(f: &dyn Fn(f64) -> f64, a: f64, b: f64, tol: f64) -> (f64, f64, f64) {
+    const RATIO: f64 = 0.618_033_988_749_895;
+    let (mut a, mut b) = (a.min(b), a.max(b));
+    let mut c = b - RATIO * (b - a);
+    let mut d = a + RATIO * (b - a);
+    let (mut fc, mut fd) = (f(c), f(d));
+    for _ in 0..200 {
+        if (b - a).abs() <= tol * (c.abs() + d.abs()).max(1.0) {
+            break;
+        }
+        if fc < fd {
+            b = d;
+            d = c;
+            fd = fc;
+            c = b - RATIO * (b - a);
+            fc = f(c);
+        }
+        else {
+            a = c;
+            c = d;
+            fc = fd;
+            d = a + RATIO * (b - a);
+            fd = f(d);
+        }
+    }
+    if fc < fd { (c, fc, b - a) } else { (d, fd, b - a) }
+}
+
+/// Minimizes a function of several parameters with the Nelder-Mead simplex method.
+///
+/// # Arguments
+/// * `f` - The function to minimize.
+/// * `guess` - The starting point.
+/// * `tol` - The relative spread of simplex values at which the search has converged.
+///
+/// # Returns
+/// The argument of the minimum, the minimum and the spread of the final simplex values.
+pub fn nelder_mead(f: &dyn Fn(&[f64]) -> f64, guess: Vec<f64>, tol: f64) -> (Vec<f64>, f64, f64) {
+    let n = guess.len();
+    let mut simplex: Vec<(Vec<f64>, f64)> = vec![(guess.clone(), f(&guess))];
+    for i in 0..n {
+        let mut vertex = guess.clone();
+        vertex[i] += if vertex[i] == 0.0 { 0.00025 } else { 0.05 * vertex[i] };
+        let value = f(&vertex);
+        simplex.push((vertex, value));
+    }
+    // moves every coordinate of `from` towards or away from `to` by `factor`
+    let towards = |from: &[f64], to: &[f64], factor: f64| -> Vec<f64> {
+        from.iter().zip(to).map(|(x, y)| x + factor * (y - x)).collect()
+    };
+
+    for _ in 0..1000 * n {
+        simplex.sort_by(|x, y| x.1.total_cmp(&y.1));
+        let (best, worst) = (simplex[0].1, simplex[n].1);
+        if (worst - best).abs() <= tol * (best.abs() + worst.abs()).max(1e-30) {
+            break;
+        }
+        let centroid: Vec<f64> = (0..n).map(|j| simplex[..n].iter().map(|v| v.0[j]).sum::<f64>() / n as f64).collect();
+        let reflected = towards(&simplex[n].0, &centroid, 2.0);
+        let f_reflected = f(&reflected);
+        if f_reflected < best {
+            let expanded = towards(&simplex[n].0, &centroid, 3.0);
+            let f_expanded = f(&expanded);
+            simplex[n] = if f_expanded < f_reflected { (expanded, f_expanded) } else { (reflected, f_reflected) };
+        }
+        else if f_reflected < simplex[n - 1].1 {
+            simplex[n] = (reflected, f_reflected);
+        }
+        else {
+            let contracted = towards(&simplex[n].0, &centroid, 0.5);
+            let f_contracted = f(&contracted);
+            if f_contracted < simplex[n].1 {
+                simplex[n] = (contracted, f_contracted);
+            }
+            else {
+                // shrink everything towards the best vertex
+                let first = simplex[0].0.clone();
+                for vertex in simplex.iter_mut().skip(1) {
+                    vertex.0 = towards(&first, &vertex.0, 0.5);
+                    vertex.1 = f(&vertex.0);
+                }
+            }
+        }
+    }
+    simplex.sort_by(|x, y| x.1.total_cmp(&y.1));
+    let spread = simplex[n].1 - simplex[0].1;
+    let (point, value) = simplex.swap_remove(0);
+    (point, value, spread)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::run_script;
+    use crate::limits::Limits;
+    use std::f64::consts::{E, PI};
+
+    fn close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance, "{} is not {}", actual, expected);
+    }
+
+    #[test]
+    fn integrals_derivatives_and_limits() {
+        let (value, error) = integrate(&|x: f64| x.sin(), 0.0, PI, DEFAULT_TOLERANCE);
+        close(value, 2.0, 1e-12);
+        assert!(error <= DEFAULT_TOLERANCE, "{}", error);
+        close(integrate(&|x: f64| (-x * x).exp(), f64::NEG_INFINITY, f64::INFINITY, DEFAULT_TOLERANCE).0, PI.sqrt(), 1e-9);
+        close(integrate(&|x: f64| 1.0 / x.sqrt(), 0.0, 1.0, DEFAULT_TOLERANCE).0, 2.0, 1e-6);
+        close(derivative(&|x: f64| x.exp(), 1.0, DEFAULT_TOLERANCE).0, E, 1e-8);
+        close(derivative(&|x: f64| x.powi(3), 2.0, DEFAULT_TOLERANCE).0, 12.0, 1e-8);
+        close(limit(&|x: f64| x.sin() / x, 0.0, 0.0).0, 1.0, 1e-9);
+        close(limit(&|x: f64| (1.0 + x).powf(1.0 / x), 0.0, 1.0).0, E, 1e-6);
+    }
+
+    #[test]
+    fn roots_of_equations_and_polynomials() {
+        let f = |x: f64| x * x - 2.0;
+        close(newton(&f, 1.0, DEFAULT_TOLERANCE).unwrap().0, 2f64.sqrt(), 1e-12);
+        close(brent(&f, 0.0, 2.0, DEFAULT_TOLERANCE).unwrap().0, 2f64.sqrt(), 1e-10);
+        assert!(brent(&f, 2.0, 3.0, DEFAULT_TOLERANCE).is_none());
+        let (a, b) = bracket(&|x: f64| x.cos(), 1.0).unwrap();
+        assert!(a.cos() * b.cos() <= 0.0);
+
+        // x^3 - 6x^2 + 11x - 6 = (x - 1)(x - 2)(x - 3), coefficients from the highest power
+        let mut roots: Vec<f64> = polynomial_roots(&[1.0, -6.0, 11.0, -6.0]).iter().map(|x| x.re).collect();
+        roots.sort_by(|x, y| x.total_cmp(y));
+        for (root, expected) in roots.iter().zip([1.0, 2.0, 3.0]) {
+            close(*root, expected, 1e-10);
+        }
+        let mut complex: Vec<(f64, f64)> = polynomial_roots(&[1.0, 0.0, 1.0]).iter().map(|x| (x.re, x.im)).collect();
+        complex.sort_by(|x, y| x.1.total_cmp(&y.1));
+        close(complex[0].0, 0.0, 1e-12);
+        close(complex[0].1, -1.0, 1e-12);
+        close(complex[1].1, 1.0, 1e-12);
+
+        // x + y = 3 and x - y = 1
+        let functions: Vec<Callback> = vec![Box::new(|v| v[0] + v[1] - 3.0), Box::new(|v| v[0] - v[1] - 1.0)];
+        let (solution, _) = newton_system(&functions, vec![0.0, 0.0], DEFAULT_TOLERANCE).unwrap();
+        close(solution[0], 2.0, 1e-10);
+        close(solution[1], 1.0, 1e-10);
+    }
+
+    #[test]
+    fn minima_and_maxima() {
+        let (x, fx, _) = golden_section(&|x: f64| (x - 2.0).powi(2) + 1.0, 0.0, 5.0, DEFAULT_TOLERANCE);
+        close(x, 2.0, 1e-6);
+        close(fx, 1.0, 1e-12);
+        let (a, b) = bracket_minimum(&|x: f64| (x + 7.0).powi(2), 0.0, "minimize", "below");
+        assert!(a.min(b) <= -7.0 && -7.0 <= a.max(b));
+        let rosenbrock = |v: &[f64]| (1.0 - v[0]).powi(2) + 100.0 * (v[1] - v[0] * v[0]).powi(2);
+        let (point, value, _) = nelder_mead(&rosenbrock, vec![-1.0, 2.0], DEFAULT_TOLERANCE);
+        close(point[0], 1.0, 1e-4);
+        close(point[1], 1.0, 1e-4);
+        close(value, 0.0, 1e-8);
+    }
+
+    #[test]
+    fn scripts_call_the_numeric_functions() {
+        let script = "f(X) => X^2 - 2\ng(X) => -(X - 1)^2 + 4\n\
+            displayln(roots({1, -3, 2}), \" \", croots({1, 0, 4}), \" \", maximize(g, 0, 3))\n\
+            displayln(round(solve(f, 1) * 1e6), \" \", round(integrate(f, 0, 3) * 1e6), \" \", round(nderiv(f, 3) * 1e6))";
+        let output = run_script(script, "", Limits::default()).unwrap();
+        assert_eq!(output.lines().nth(1).unwrap(), "1414214 3000000 6000000");
+        assert!(output.starts_with("{1.0000000000000002, 2} {0, 2, 0, -2} {1.00000001"), "{}", output);
+    }
+
+    #[test]
+    fn error_estimates_belong_to_a_run() {
+        let script = "f(X) => 1 / (1 + X^2)\nintegrate(f, 0, 3)\ndisplayln(error() > 0, \" \", error() < 1e-10)";
+        assert_eq!(run_script(script, "", Limits::default()).unwrap(), "1 1\n");
+        // a run starts without an estimate, whatever the ones before it made
+        assert_eq!(run_script("displayln(error())", "", Limits::default()).unwrap(), "0\n");
+        assert_eq!(run_script("f(X) => X\nsummation(f, 1, 4)\ndisplayln(error())", "", Limits::default()).unwrap(), "0\n");
+    }
+}
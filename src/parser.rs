@@ -1,445 +1,729 @@
-use crate::lexer::{Line, Token, TokenType};
-use crate::interpreter::{Marker, MARKERS};
+use crate::diagnostic::Diagnostic;
+use crate::lexer::{number_value, Line, Span, Token, TokenType};
+use crate::limits::Limits;
+use std::fmt;
 use std::vec::Vec;
 
-#[derive(Debug, Clone)]
-pub struct ExprNode {
-    pub c: String,
-    pub token: Token,
-    pub line: i32,
-    pub operand1: Option<Box<ExprNode>>,
-    pub operand2: Option<Box<ExprNode>>,
-    pub func_parameters: Option<Vec<Vec<Option<ExprNode>>>>,
-    pub func_name: Option<String>,
-    pub marker_line: Option<i32>,
-    pub marker_num: Option<i32>,
-    pub set: Option<Vec<Vec<Option<ExprNode>>>>
+/// An operator between two expressions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Add, Subtract, Multiply, Divide, Remainder, Power,
+    Equal, NotEqual, Greater, GreaterOrEqual, Less, LessOrEqual,
+    And, Or, Xor
 }
-
-impl ExprNode {
-    pub fn is_func(&self) -> bool {
-        self.func_name.is_some() && self.func_parameters.is_some() 
-    }
-    fn new_num(num: Token, line: i32) -> Self {
-        ExprNode {
-            c: num.value.clone(),
-            token: num,
-            line: line,
-            operand1: None,
-            operand2: None,
-            func_parameters: None,
-            func_name: None,
-            marker_line: None,
-            marker_num: None,
-            set: None
-        }
+impl fmt::Display for BinaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Subtract => "-",
+            BinaryOp::Multiply => "*",
+            BinaryOp::Divide => "/",
+            BinaryOp::Remainder => "%",
+            BinaryOp::Power => "^",
+            BinaryOp::Equal => "=",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::Greater => ">",
+            BinaryOp::GreaterOrEqual => ">=",
+            BinaryOp::Less => "<",
+            BinaryOp::LessOrEqual => "<=",
+            BinaryOp::And => " and ",
+            BinaryOp::Or => " or ",
+            BinaryOp::Xor => " xor ",
+        };
+        write!(f, "{}", symbol)
     }
-    fn new_op(op: Token, e1: ExprNode, e2: ExprNode, line: i32) -> Self {
-        ExprNode {
-            c: op.value.clone(),
-            token: op,
-            line: line,
-            operand1: Some(Box::new(e1)),
-            operand2: Some(Box::new(e2)),
-            func_parameters: None,
-            func_name: None,
-            marker_line: None,
-            marker_num: None,
-            set: None
-        }
+}
+
+/// An operator on a single expression.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOp {
+    Negate, Plus, Not, Factorial
+}
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            UnaryOp::Negate => "-",
+            UnaryOp::Plus => "+",
+            UnaryOp::Not => "not ",
+            UnaryOp::Factorial => "!",
+        };
+        write!(f, "{}", symbol)
     }
-    fn new_func(func: Token, parameters: Option<Vec<Vec<Option<ExprNode>>>>, line: i32) -> Self {
-        ExprNode {
-            c: String::new(),
-            token: func.clone(),
-            line: line,
-            operand1: None,
-            operand2: None,
-            func_parameters: parameters,
-            func_name: Some(func.value.clone()),
-            marker_line: None,
-            marker_num: None,
-            set: None
+}
+
+/// An expression or statement, each carrying the span of source it was parsed from.
+#[derive(Clone, Debug)]
+pub enum Expr {
+    Number { value: f64, span: Span },
+    Text { value: String, span: Span },
+    /// A variable (uppercase) or constant (lowercase).
+    Variable { name: String, span: Span },
+    Binary { op: BinaryOp, left: Box<Expr>, right: Box<Expr>, span: Span },
+    /// Chained comparisons such as `0 < X < 10`, each operand between two comparisons evaluated once.
+    Chain { operands: Vec<Expr>, ops: Vec<BinaryOp>, span: Span },
+    Unary { op: UnaryOp, operand: Box<Expr>, span: Span },
+    Call { name: String, arguments: Vec<Expr>, span: Span },
+    Set { elements: Vec<Expr>, span: Span },
+    /// `set_index`, counting from 1.
+    Index { set: Box<Expr>, index: Box<Expr>, span: Span },
+    /// `condition: value`, followed by `; otherwise` when there is an else.
+    Conditional { condition: Box<Expr>, value: Box<Expr>, otherwise: Option<Box<Expr>>, span: Span },
+    /// `value -> name`, the target span pointing at the name.
+    Assign { value: Box<Expr>, name: String, target: Span, span: Span },
+    /// `name(parameters) => body`, with the doc comment written before it.
+    FuncDef { name: String, parameters: Vec<String>, body: Box<Expr>, doc: Option<String>, span: Span },
+    /// `$mark`, a target for `goto`.
+    Marker { mark: i32, span: Span },
+    /// `import "path"`, optionally `as namespace` to call its functions as `namespace.name`.
+    Import { path: String, namespace: Option<String>, span: Span }
+}
+
+impl Expr {
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Number { span, .. } | Expr::Text { span, .. } | Expr::Variable { span, .. } |
+            Expr::Binary { span, .. } | Expr::Chain { span, .. } | Expr::Unary { span, .. } | Expr::Call { span, .. } |
+            Expr::Set { span, .. } | Expr::Index { span, .. } | Expr::Conditional { span, .. } |
+            Expr::Assign { span, .. } | Expr::FuncDef { span, .. } | Expr::Marker { span, .. } |
+            Expr::Import { span, .. } => *span,
         }
     }
-    fn new_marker(token: Token, line: i32, number: i32) -> Self {
-        ExprNode {
-            c: String::new(),
-            token: token.clone(),
-            line: line,
-            operand1: None,
-            operand2: None,
-            func_parameters: None,
-            func_name: None,
-            marker_line: Some(line.clone()),
-            marker_num: Some(number.clone()),
-            set: None
+}
+
+/// Binding power of implicit multiplication such as `2X` or `4(AC)`, tighter than `*` and `/` so `1/2X` is `1/(2X)`.
+const IMPLICIT_MULTIPLICATION: (u8, u8) = (19, 20);
+/// Binding power of prefix `-` and `+`, looser than `^` so `-2^2` is `-(2^2)`.
+const PREFIX: u8 = 21;
+/// Binding power of `not`, looser than comparisons so `not X > 2` is `not (X > 2)`.
+const NOT: u8 = 11;
+/// Binding power of postfix `!`.
+const POSTFIX: u8 = 27;
+
+/// Returns the left and right binding power of an infix operator, higher binding tighter.
+/// A left power greater than the right makes the operator right-associative.
+pub fn precedence(token_type: &TokenType) -> Option<(u8, u8)> {
+    let power = match token_type {
+        TokenType::Semicolon => (1, 2),
+        TokenType::Colon => (4, 3),
+        TokenType::Or => (5, 6),
+        TokenType::Xor => (7, 8),
+        TokenType::And => (9, 10),
+        TokenType::Equal | TokenType::NotEqual | TokenType::GreaterThan | TokenType::GreaterThanOrEqualTo |
+        TokenType::LessThan | TokenType::LessThanOrEqualTo => (13, 14),
+        TokenType::Plus | TokenType::Dash => (15, 16),
+        TokenType::Star | TokenType::Slash | TokenType::Percantage => (17, 18),
+        TokenType::Carrot => (24, 23),
+        TokenType::Underscore => (25, 26),
+        _ => return None,
+    };
+    Some(power)
+}
+
+/// Parses a list of lexer lines into expression trees, continuing past lines with syntax errors.
+///
+/// # Arguments
+/// * `lexer_lines` - A vector of lexer lines to be parsed.
+///
+/// # Returns
+/// A vector of optional statements representing the parsed input, several for lines separating them with `,`
+/// and `None` for lines with errors, and the syntax errors of every line.
+pub fn parse(lexer_lines: Vec<Line>) -> (Vec<Option<Expr>>, Vec<Diagnostic>) {
+    parse_with(lexer_lines, &Limits::default())
+}
+
+/// Parses a list of lexer lines like `parse`, allowing brackets to nest as deeply as `limits.nesting`.
+pub fn parse_with(lexer_lines: Vec<Line>, limits: &Limits) -> (Vec<Option<Expr>>, Vec<Diagnostic>) {
+    let mut returns: Vec<Option<Expr>> = Vec::new();
+    let mut diagnostics: Vec<Diagnostic> = Vec::new();
+
+    for l in &lexer_lines {
+        match parse_line(l, limits.nesting) {
+            Ok(statements) => returns.extend(statements.into_iter().map(Some)),
+            Err(mut errors) => {
+                diagnostics.append(&mut errors);
+                returns.push(None);
+            }
         }
     }
-    fn new_set(token: Token, indexes: Option<Vec<Vec<Option<ExprNode>>>>, line_num: i32) -> Self {
-        ExprNode {
-            c: String::new(),
-            token: token.clone(),
-            line: line_num,
-            operand1: None,
-            operand2: None,
-            func_parameters: None,
-            func_name: None,
-            marker_line: None,
-            marker_num: None,
-            set: indexes.clone()
+    (returns, diagnostics)
+}
+
+/// Parses a single lexer line into statements, separated by commas outside of brackets such as `1 -> A, 2 -> B`.
+///
+/// # Arguments
+/// * `l` - The lexer line to be parsed.
+/// * `max_nesting` - The depth brackets may nest to.
+///
+/// # Returns
+/// The statements of the line, or the syntax errors found in it.
+fn parse_line(l: &Line, max_nesting: Option<usize>) -> Result<Vec<Expr>, Vec<Diagnostic>> {
+    let mut parser = Parser { tokens: &l.tokens, position: 0, errors: Vec::new(), depth: 0, max_nesting, too_deep: false };
+    let mut statements = Vec::new();
+
+    while parser.peek().is_some() {
+        let start = parser.position;
+        match parser.statement() {
+            Ok(statement) => statements.push(statement),
+            Err(error) => {
+                parser.errors.push(error);
+                if parser.too_deep {
+                    // skipping the whole statement, its brackets left open where parsing stopped
+                    parser.position = start;
+                    parser.too_deep = false;
+                }
+                parser.skip_statement();
+            }
         }
+        // the separator
+        parser.next();
     }
+    if parser.errors.is_empty() { Ok(statements) } else { Err(parser.errors) }
 }
 
-pub fn precedence(op: String) -> i32 {
-    match op.as_str() {
-        "->"| "=>" => -1,
-        ";" => 1,
-        ":" => 3,
-        "=" => 3,
-        ">" | "<" | ">=" | "<=" => 4,
-        "+" | "-" => 5,
-        "*" | "/" => 6,
-        "^" | "_" => 7,
-        "%" => 8,
-        _ => 0,
-    }
+/// A precedence-climbing parser over the tokens of one line.
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    /// Errors recovered from inside sets and function calls.
+    errors: Vec<Diagnostic>,
+    /// Brackets, calls and operands of prefix and right-associative operators being parsed inside each other,
+    /// the only expressions that nest without a bound.
+    depth: usize,
+    max_nesting: Option<usize>,
+    /// Whether the statement being parsed nests too deeply, so it is skipped instead of recovered from.
+    too_deep: bool
 }
 
-/// Parses a list of lexer lines into an expression tree.
-/// 
-/// # Arguments
-/// * `lexer_lines` - A vector of lexer lines to be parsed.
-/// 
-/// # Returns
-/// A vector of optional expression nodes representing the parsed input.
-pub fn parse(lexer_lines: Vec<Line>) -> Vec<Option<ExprNode>> {
-    let mut lines = lexer_lines.iter().peekable();
-    let mut returns: Vec<Option<ExprNode>> = Vec::new();
-
-    while let Some(l) = lines.peek() {
-        let mut chars = l.tokens.iter().peekable();
-        let char_len = chars.clone().count();
-        let mut last_was_digit_or_closing = false;
-        let mut last_was_variable = false;
-        let mut operator_stack: Vec<Token> = Vec::new();
-        let mut expr_stack: Vec<ExprNode> = Vec::new();
-        let number = l.number;
-
-        while let Some(c) = chars.peek() {
-            match c.token_type {
-                TokenType::OpenParen => {
-                    if last_was_digit_or_closing || last_was_variable {
-                        // Implicit multiplication: e.g., "2(" or ")("
-                        operator_stack.push(Token { value: "*".to_string(), token_type: TokenType::Star });
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.position)
+    }
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+    /// Returns an empty span just after the last token, for errors at the end of the line.
+    fn end_span(&self) -> Span {
+        let last = self.tokens[self.tokens.len() - 1].span;
+        Span { offset: last.offset + last.length, length: 0, column: last.column + last.length, ..last }
+    }
+
+    /// Parses a marker, assignment, function definition or expression, which must use every token up to a separator.
+    fn statement(&mut self) -> Result<Expr, Diagnostic> {
+        // a doc comment documents the function defined after it
+        let doc = self.peek().filter(|x| x.token_type == TokenType::Doc).map(|x| x.value.clone());
+        if doc.is_some() {
+            self.next();
+        }
+        if self.peek().is_some_and(|x| x.token_type == TokenType::DollarSign) {
+            return self.marker();
+        }
+        if self.peek().is_some_and(|x| x.token_type == TokenType::Import) {
+            return self.import();
+        }
+        let expr = self.expression(0)?;
+        let statement = match self.peek().map(|x| &x.token_type) {
+            Some(TokenType::Arrow) => {
+                self.next();
+                match self.peek() {
+                    Some(name) if name.token_type == TokenType::Uppercase || name.token_type == TokenType::Lowercase => {
+                        self.next();
+                        let span = expr.span().to(name.span);
+                        Expr::Assign { value: Box::new(expr), name: name.value.clone(), target: name.span, span }
                     }
-                    operator_stack.push((*c).clone());
-                    chars.next();
-                    last_was_digit_or_closing = false;
-                    last_was_variable = false;
+                    Some(other) => return Err(Diagnostic::new(format!("Expected a variable or constant name after '->', got '{}'", other.value)).at(other.span)),
+                    None => return Err(Diagnostic::new("Expected a variable or constant name after '->'").at(self.end_span())),
                 }
-                TokenType::Number => {
-                    expr_stack.push(ExprNode::new_num((*c).clone(), number));
-                    chars.next();
-                    last_was_digit_or_closing = true;
-                    last_was_variable = false;
+            }
+            Some(TokenType::DoubleArrow) => {
+                self.next();
+                self.function_definition(expr, doc)?
+            }
+            _ => expr,
+        };
+        match self.peek() {
+            None => Ok(statement),
+            Some(token) if token.token_type == TokenType::Comma => Ok(statement),
+            Some(token) => Err(self.unexpected(token)),
+        }
+    }
+
+    /// Parses `$mark` at the start of a line, which `goto` jumps to.
+    fn marker(&mut self) -> Result<Expr, Diagnostic> {
+        let dollar = self.next().unwrap();
+        let Some(mark_token) = self.next() else {
+            return Err(Diagnostic::new("Expected a number after '$'").at(dollar.span));
+        };
+        let Ok(mark) = mark_token.value.parse::<i32>() else {
+            return Err(Diagnostic::new(format!("Expected a whole number after '$', got '{}'", mark_token.value)).at(mark_token.span));
+        };
+        if let Some(token) = self.peek().filter(|x| x.token_type != TokenType::Comma) {
+            return Err(Diagnostic::new("Marker syntax is incorrect, expected '$' at the start of line and a number after it: '$0'").at(token.span));
+        }
+        Ok(Expr::Marker { mark, span: dollar.span.to(mark_token.span) })
+    }
+
+    /// Parses `import "path"`, optionally followed by `as namespace`.
+    fn import(&mut self) -> Result<Expr, Diagnostic> {
+        let keyword = self.next().unwrap();
+        let path = match self.next() {
+            Some(token) if token.token_type == TokenType::Text => token,
+            Some(token) => return Err(Diagnostic::new(format!("Expected the path of a file in quotes after 'import', got '{}'", token.value)).at(token.span)),
+            None => return Err(Diagnostic::new("Expected the path of a file in quotes after 'import'").at(self.end_span())),
+        };
+        let mut span = keyword.span.to(path.span);
+        let mut namespace = None;
+        if self.peek().is_some_and(|x| x.token_type == TokenType::Lowercase && x.value == "as") {
+            self.next();
+            match self.next() {
+                Some(name) if name.token_type == TokenType::Lowercase => {
+                    namespace = Some(name.value.clone());
+                    span = span.to(name.span);
                 }
-                TokenType::Dash => {
-                    if !last_was_digit_or_closing && !last_was_variable {
-                        chars.next();
-                        let operator = Token { value: "-".to_string(), token_type: TokenType::Dash };
-                        let e1 = ExprNode::new_num(Token { token_type: TokenType::Number, value: "0".to_string() }, number);
-                        let e2 = ExprNode::new_num(chars.peek().unwrap().clone().clone(), number);
-                        expr_stack.push(ExprNode::new_op(operator, e1, e2, number));
-                    } else {
-                        while operator_stack.last().map_or(false, |top| precedence(top.value.clone()) >= precedence(c.value.clone())) && expr_stack.len() > 1 {
-                            let operator = operator_stack.pop().unwrap();
-                            let e2 = expr_stack.pop().unwrap();
-                            let e1 = expr_stack.pop().unwrap();
-                            expr_stack.push(ExprNode::new_op(operator, e1, e2, number));
-                        }
-                        operator_stack.push((*c).clone());
-                    }
-                    chars.next();
-                    last_was_digit_or_closing = false;
-                    last_was_variable = false;
+                Some(token) => return Err(Diagnostic::new(format!("Expected a lowercase namespace after 'as', got '{}'", token.value)).at(token.span)),
+                None => return Err(Diagnostic::new("Expected a lowercase namespace after 'as'").at(self.end_span())),
+            }
+        }
+        match self.peek() {
+            None => Ok(Expr::Import { path: path.value.clone(), namespace, span }),
+            Some(token) if token.token_type == TokenType::Comma => Ok(Expr::Import { path: path.value.clone(), namespace, span }),
+            Some(token) => Err(self.unexpected(token)),
+        }
+    }
+
+    /// Parses the body of `name(parameters) => body`, the head having been parsed as a call.
+    fn function_definition(&mut self, head: Expr, doc: Option<String>) -> Result<Expr, Diagnostic> {
+        let Expr::Call { name, arguments, span: head_span } = head else {
+            return Err(Diagnostic::new("Expected a function such as 'f(X)' before '=>'").at(head.span()));
+        };
+        let mut parameters = Vec::new();
+        for argument in &arguments {
+            match argument {
+                Expr::Variable { name, .. } if name.chars().all(|c| c.is_uppercase()) => parameters.push(name.clone()),
+                other => return Err(Diagnostic::new("Expected the parameters of a function to be variables").at(other.span())),
+            }
+        }
+        let body = self.expression(0)?;
+        let span = head_span.to(body.span());
+        Ok(Expr::FuncDef { name, parameters, body: Box::new(body), doc, span })
+    }
+
+    /// Parses an expression inside the one being parsed, failing once they are nested too deeply.
+    fn nested(&mut self, parse: impl FnOnce(&mut Self) -> Result<Expr, Diagnostic>) -> Result<Expr, Diagnostic> {
+        if let Some(max) = self.max_nesting.filter(|x| self.depth >= *x) {
+            let span = self.peek().map_or_else(|| self.end_span(), |x| x.span);
+            self.too_deep = true;
+            let hint = "the limit can be raised with `Limits::nesting`";
+            return Err(Diagnostic::new(format!("Expression nested more than {} deep", max)).at(span).with_code("nesting-limit").with_hint(Some(hint.to_string())));
+        }
+        self.depth += 1;
+        let expr = parse(self);
+        self.depth -= 1;
+        expr
+    }
+
+    /// Parses an expression whose operators bind at least as tightly as `min_power`.
+    fn expression(&mut self, min_power: u8) -> Result<Expr, Diagnostic> {
+        let mut left = self.prefix()?;
+
+        while let Some(token) = self.peek() {
+            if token.token_type == TokenType::Exclamation {
+                if POSTFIX < min_power {
+                    break;
                 }
-                TokenType::Plus | TokenType::Star | TokenType::Slash | TokenType::Arrow | TokenType::Equal | TokenType::Percantage | 
-                TokenType::Carrot | TokenType::Colon | TokenType::GreaterThan | TokenType::GreaterThanOrEqualTo | TokenType::LessThan | TokenType::LessThanOrEqualTo | 
-                TokenType::Comma | TokenType::Semicolon | TokenType::Underscore | TokenType::DoubleArrow => {
-                    while operator_stack.last().map_or(false, |top| precedence(top.value.clone()) >= precedence(c.value.clone())) && expr_stack.len() > 1 {
-                        let operator = operator_stack.pop().unwrap();
-                        let e2 = expr_stack.pop().unwrap();
-                        let e1 = expr_stack.pop().unwrap();
-                        expr_stack.push(ExprNode::new_op(operator, e1, e2, number));
-                    }
-                    operator_stack.push((*c).clone());
-                    chars.next();
-                    last_was_digit_or_closing = false;
-                    last_was_variable = false;
+                self.next();
+                let span = left.span().to(token.span);
+                left = Expr::Unary { op: UnaryOp::Factorial, operand: Box::new(left), span };
+            }
+            else if let Some((left_power, right_power)) = precedence(&token.token_type) {
+                if left_power < min_power {
+                    break;
                 }
-                TokenType::Exclamation => {
-                    let operator = Token { value: "!".to_string(), token_type: TokenType::Exclamation };
-                    let e1 = expr_stack.pop().unwrap();
-                    let e2 = ExprNode::new_num(Token { token_type: TokenType::None, value: String::new() }, number);
-                    expr_stack.push(ExprNode::new_op(operator, e1, e2, number));
-
-                    chars.next();
-                    last_was_digit_or_closing = false;
-                    last_was_variable = false;
+                self.next();
+                let right = if left_power > right_power {
+                    // a right-associative operand, nesting with every operator after it
+                    self.nested(|parser| parser.expression(right_power))?
                 }
-                TokenType::CloseParen => {
-                    while operator_stack.last().map_or(false, |op| op.token_type != TokenType::OpenParen) && expr_stack.len() > 1 {
-                        let operator = operator_stack.pop().unwrap();
-                        let e2 = expr_stack.pop().unwrap();
-                        let e1 = expr_stack.pop().unwrap();
-                        expr_stack.push(ExprNode::new_op(operator, e1, e2, number));
+                else {
+                    self.expression(right_power)?
+                };
+                left = infix(token, left, right)?;
+                if let Expr::Binary { op, .. } = &left {
+                    if is_comparison(*op) && self.peek().is_some_and(|x| comparison(&x.token_type).is_some()) {
+                        left = self.chain(left)?;
                     }
-                    operator_stack.pop(); // Pop the '('
-                    chars.next();
-                    last_was_digit_or_closing = true;
-                    last_was_variable = false;
                 }
-                TokenType::Uppercase => {
-                    if last_was_digit_or_closing || last_was_variable {
-                        operator_stack.push(Token { value: "*".to_string(), token_type: TokenType::Star });
-                    }
-                    expr_stack.push(ExprNode::new_num((*c).clone(), number));
-                    chars.next();
-                    last_was_digit_or_closing = true;
-                    last_was_variable = true;
+            }
+            else if matches!(token.token_type, TokenType::Uppercase | TokenType::Lowercase | TokenType::OpenParen | TokenType::Root) {
+                // implicit multiplication: e.g., "2X", "2(", ")(" or "2√X"
+                let (left_power, right_power) = IMPLICIT_MULTIPLICATION;
+                if left_power < min_power {
+                    break;
                 }
-                TokenType::Lowercase => {
-                    // Get all tokens inside the parentheses
-                    let mut params_tokens_inside_parens: Vec<Vec<Token>> = vec![vec![]];
-                    let mut params_index = 0;
-                    let mut parenthesis_count = 0;
-                    let mut index = 0;
-                    let name = (*c).clone();
-                    chars.next();
-
-                    while let Some(c) = chars.peek() {
-                        match c.token_type {
-                            TokenType::OpenParen => {
-                                index += 1;
-                                parenthesis_count += 1;
-                                if parenthesis_count > 1 {
-                                    params_tokens_inside_parens[params_index].push((*c).clone());
-                                }
-                                chars.next();
-                            }
-                            TokenType::CloseParen => {
-                                if index == 0 {
-                                    break;
-                                }
-                                index += 1;
-                                parenthesis_count -= 1;
-                                let mut breaks = false;
-                                if parenthesis_count == 0 {
-                                    breaks = true;
-                                }
-                                else {
-                                    params_tokens_inside_parens[params_index].push((*c).clone());
-                                }
-                                chars.next();
-                                
-                                if breaks{
-                                    break;
-                                }
-                            }
-                            TokenType::Comma => {
-                                if index == 0 {
-                                    break;
-                                }
-                                index += 1;
-                                if parenthesis_count == 1 {
-                                    params_tokens_inside_parens.push(vec![]);
-                                    params_index += 1;
-                                }
-                                else {
-                                    params_tokens_inside_parens[params_index].push((*c).clone());
-                                }
-                                chars.next();
-                            }
-                            _ => {
-                                if index == 0 {
-                                    break;
-                                }
-                                index += 1;
-                                params_tokens_inside_parens[params_index].push((*c).clone());
-                                chars.next();
-                            }
-                        }
-                    }
+                let right = self.expression(right_power)?;
+                let span = left.span().to(right.span());
+                left = Expr::Binary { op: BinaryOp::Multiply, left: Box::new(left), right: Box::new(right), span };
+            }
+            else {
+                break;
+            }
+        }
+        Ok(left)
+    }
 
-                    if index == 0 {
-                        // is constant
-                        if last_was_digit_or_closing || last_was_variable {
-                            operator_stack.push(Token { value: "*".to_string(), token_type: TokenType::Star });
-                        }
+    /// Continues a comparison into a chain such as `0 < X < 10`, the first comparison having been parsed.
+    fn chain(&mut self, first: Expr) -> Result<Expr, Diagnostic> {
+        let Expr::Binary { op, left, right, span } = first else {
+            return Ok(first);
+        };
+        let mut operands = vec![*left, *right];
+        let mut ops = vec![op];
+        let mut span = span;
+        while let Some(op) = self.peek().and_then(|x| comparison(&x.token_type)) {
+            self.next();
+            let (_, right_power) = precedence(&TokenType::Equal).unwrap();
+            let operand = self.expression(right_power)?;
+            span = span.to(operand.span());
+            operands.push(operand);
+            ops.push(op);
+        }
+        Ok(Expr::Chain { operands, ops, span })
+    }
 
-                        expr_stack.push(ExprNode::new_num(name.clone(), number));
-                        last_was_digit_or_closing = true;
-                        last_was_variable = true;
-                    }
-                    else {
-                        // is function
-                        let mut parsed_tokens: Vec<Vec<Option<ExprNode>>> = Vec::new();
-                        if params_tokens_inside_parens[0].len() != 0 {   
-                            for tokens_inside_parens in params_tokens_inside_parens {
-                                parsed_tokens.push(parse(vec![Line { number: l.number, tokens: tokens_inside_parens }]));
-                            }
-                        }
-                        
-                        expr_stack.push(ExprNode::new_func(name, Some(parsed_tokens), number));
-                        
-                        last_was_digit_or_closing = true;
-                        last_was_variable = false;
-                    }
-                }
-                TokenType::DollarSign => {
-                    if !operator_stack.is_empty() && !expr_stack.is_empty() {
-                        panic!("Expected '$' to be at the start of line in line {}", number);
-                    }
-                    if char_len > 2 {
-                        panic!("marker_line syntax is incorrect. Expected '$' to be at the start of line and number after it: '$0' in line {}", number);
+    /// Parses a value, a prefix operator with its operand, a parenthesised expression, a set or a call.
+    fn prefix(&mut self) -> Result<Expr, Diagnostic> {
+        let Some(token) = self.next() else {
+            return Err(Diagnostic::new("Expected a value").at(self.end_span()));
+        };
+        match token.token_type {
+            TokenType::Number => match number_value(&token.value) {
+                Some(value) => Ok(Expr::Number { value, span: token.span }),
+                None => Err(Diagnostic::new(format!("Invalid number '{}'", token.value)).at(token.span)),
+            },
+            TokenType::Text => Ok(Expr::Text { value: token.value.clone(), span: token.span }),
+            TokenType::Uppercase => Ok(Expr::Variable { name: token.value.clone(), span: token.span }),
+            TokenType::Not => {
+                let operand = self.nested(|parser| parser.expression(NOT))?;
+                let span = token.span.to(operand.span());
+                Ok(Expr::Unary { op: UnaryOp::Not, operand: Box::new(operand), span })
+            }
+            TokenType::Lowercase => {
+                // a name from an imported namespace, such as "trig.sin"
+                let mut name = token.value.clone();
+                let mut span = token.span;
+                while let (Some(dot), Some(member)) = (self.peek(), self.tokens.get(self.position + 1)) {
+                    let adjacent = dot.span.offset == span.offset + span.length && member.span.offset == dot.span.offset + dot.span.length;
+                    if dot.token_type != TokenType::Dot || member.token_type != TokenType::Lowercase || !adjacent {
+                        break;
                     }
-                    let token = c.clone();
-                    chars.next();
-                    let mark = chars.next().unwrap().value.clone().parse::<i32>().unwrap();
-                    unsafe { MARKERS.push(Marker {line_num: l.number, mark}) };
-                    
-                    expr_stack.push(ExprNode::new_marker(token.clone(), number, mark));
-                    break;
+                    self.position += 2;
+                    name = format!("{}.{}", name, member.value);
+                    span = span.to(member.span);
                 }
-                TokenType::OpenCurley => {
-                    // Get all tokens inside the curleys
-                    let mut curley_tokens_inside_parens: Vec<Vec<Token>> = vec![vec![]];
-                    let mut curley_index = 0;
-                    let mut curley_count = 1;
-                    let token = (*c).clone();
-                    chars.next();
-
-                    while let Some(c) = chars.peek() {
-                        match c.token_type {
-                            TokenType::OpenCurley => {
-                                curley_count += 1;
-                                if curley_count > 1 {
-                                    curley_tokens_inside_parens[curley_index].push((*c).clone());
-                                }
-                                chars.next();
-                            }
-                            TokenType::CloseCurley => {
-                                curley_count -= 1;
-                                let mut breaks = false;
-                                if curley_count == 0 {
-                                    breaks = true;
-                                }
-                                else {
-                                    curley_tokens_inside_parens[curley_index].push((*c).clone());
-                                }
-                                chars.next();
-                                
-                                if breaks{
-                                    break;
-                                }
-                            }
-                            TokenType::Comma => {
-                                if curley_count == 1 {
-                                    curley_tokens_inside_parens.push(vec![]);
-                                    curley_index += 1;
-                                }
-                                else {
-                                    curley_tokens_inside_parens[curley_index].push((*c).clone());
-                                }
-                                chars.next();
-                            }
-                            _ => {
-                                curley_tokens_inside_parens[curley_index].push((*c).clone());
-                                chars.next();
-                            }
-                        }
-                    }
 
-                    let mut parsed_tokens: Vec<Vec<Option<ExprNode>>> = Vec::new();
-                    if curley_tokens_inside_parens[0].len() != 0 {   
-                        for tokens_inside_parens in curley_tokens_inside_parens {
-                            parsed_tokens.push(parse(vec![Line { number: l.number, tokens: tokens_inside_parens }]));
-                        }
-                    }
-                    
-                    expr_stack.push(ExprNode::new_set(token, Some(parsed_tokens), number));
-                    
-                    last_was_digit_or_closing = false;
-                    last_was_variable = false;
+                if self.peek().is_some_and(|x| x.token_type == TokenType::OpenParen) {
+                    let open = self.next().unwrap();
+                    let (arguments, close) = self.list(open, TokenType::CloseParen)?;
+                    Ok(Expr::Call { name, arguments, span: span.to(close) })
                 }
-                TokenType::CloseCurley => {
-                    panic!("Unexpected character '{}', has '}}' without opening '{{' in line {}", c.value, number); 
+                else {
+                    Ok(Expr::Variable { name, span })
+                }
+            }
+            TokenType::Dash | TokenType::Plus => {
+                let operand = self.nested(|parser| parser.expression(PREFIX))?;
+                let span = token.span.to(operand.span());
+                let op = if token.token_type == TokenType::Dash { UnaryOp::Negate } else { UnaryOp::Plus };
+                Ok(Expr::Unary { op, operand: Box::new(operand), span })
+            }
+            TokenType::OpenParen => {
+                let expr = self.nested(|parser| parser.expression(0))?;
+                match self.next() {
+                    Some(close) if close.token_type == TokenType::CloseParen => Ok(expr),
+                    Some(other) => Err(self.unexpected(other)),
+                    None => Err(Diagnostic::new("Expected ')' to close '('").at(token.span)),
                 }
-                _ => {
-                    panic!("Unexpected character '{}' in line {}", c.value, number); // Error handling
+            }
+            TokenType::Root => {
+                // √ applies to the value right after it
+                if !self.peek().is_some_and(|x| matches!(x.token_type, TokenType::Number | TokenType::Uppercase | TokenType::Lowercase | TokenType::OpenParen | TokenType::OpenCurley)) {
+                    return Err(Diagnostic::new("Expected a value right after '√'").at(token.span));
                 }
+                let operand = self.nested(Self::prefix)?;
+                let span = token.span.to(operand.span());
+                Ok(Expr::Call { name: "sqrt".to_string(), arguments: vec![operand], span })
+            }
+            TokenType::OpenCurley => {
+                let (elements, close) = self.list(token, TokenType::CloseCurley)?;
+                Ok(Expr::Set { elements, span: token.span.to(close) })
+            }
+            TokenType::DollarSign => Err(Diagnostic::new("Expected '$' to be at the start of line").at(token.span)),
+            TokenType::CloseParen | TokenType::CloseCurley | TokenType::Comma => {
+                // left for the enclosing set or call to recover at
+                self.position -= 1;
+                Err(Diagnostic::new(format!("Expected a value before '{}'", token.value)).at(token.span))
             }
+            _ => Err(self.unexpected(token)),
         }
+    }
 
-        let mut i = 0;
-        while let Some(operator) = operator_stack.pop() {
-            i += 1;
-            if i < 99 {
-                let mut e1 = ExprNode::new_num(Token { value: String::new(), token_type: TokenType::None }, number);
-                let mut e2 = ExprNode::new_num(Token { value: String::new(), token_type: TokenType::None }, number);
-                if !expr_stack.is_empty() {
-                    e2 = expr_stack.pop().unwrap();
+    /// Parses comma separated expressions up to the closing token, recovering from errors in each element.
+    ///
+    /// # Returns
+    /// The elements and the span of the closing token.
+    fn list(&mut self, open: &Token, close: TokenType) -> Result<(Vec<Expr>, Span), Diagnostic> {
+        let mut items = Vec::new();
+        if let Some(token) = self.peek().filter(|x| x.token_type == close) {
+            self.next();
+            return Ok((items, token.span));
+        }
+        loop {
+            match self.nested(|parser| parser.expression(0)) {
+                Ok(item) => items.push(item),
+                Err(error) if self.too_deep => return Err(error),
+                Err(error) => {
+                    self.errors.push(error);
+                    self.skip_element();
                 }
-                if !expr_stack.is_empty() {
-                    e1 = expr_stack.pop().unwrap();
+            }
+            loop {
+                match self.peek() {
+                    Some(token) if token.token_type == TokenType::Comma => {
+                        self.next();
+                        break;
+                    }
+                    Some(token) if token.token_type == close => {
+                        self.next();
+                        return Ok((items, token.span));
+                    }
+                    Some(token) => {
+                        let error = self.unexpected(token);
+                        self.errors.push(error);
+                        self.next();
+                        self.skip_element();
+                    }
+                    None => {
+                        let closing = if close == TokenType::CloseParen { ")" } else { "}" };
+                        return Err(Diagnostic::new(format!("Expected '{}' to close '{}'", closing, open.value)).at(open.span));
+                    }
                 }
-                expr_stack.push(ExprNode::new_op(operator, e1, e2, number));
             }
         }
+    }
 
-        returns.push(expr_stack.pop());
-        lines.next();
+    /// Skips to the end of the current set element or argument, stepping over nested brackets.
+    fn skip_element(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.peek() {
+            match token.token_type {
+                TokenType::OpenParen | TokenType::OpenCurley => depth += 1,
+                TokenType::CloseParen | TokenType::CloseCurley if depth == 0 => return,
+                TokenType::CloseParen | TokenType::CloseCurley => depth -= 1,
+                TokenType::Comma if depth == 0 => return,
+                _ => {}
+            }
+            self.next();
+        }
+    }
+
+    /// Skips to the separator ending the current statement, stepping over brackets.
+    fn skip_statement(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.peek() {
+            match token.token_type {
+                TokenType::OpenParen | TokenType::OpenCurley => depth += 1,
+                TokenType::CloseParen | TokenType::CloseCurley if depth > 0 => depth -= 1,
+                TokenType::Comma if depth == 0 => return,
+                _ => {}
+            }
+            self.next();
+        }
+    }
+
+    /// Describes a token that can not appear where it was found.
+    fn unexpected(&self, token: &Token) -> Diagnostic {
+        let message = match token.token_type {
+            TokenType::CloseParen => "Unexpected character ')', has ')' without opening '('".to_string(),
+            TokenType::CloseCurley => "Unexpected character '}', has '}' without opening '{'".to_string(),
+            TokenType::Number | TokenType::Text => "Expected an operator between values".to_string(),
+            _ => format!("Unexpected character '{}'", token.value),
+        };
+        Diagnostic::new(message).at(token.span)
     }
-    returns
 }
 
-fn index_is_none(index: &Option<Token>) -> bool {
-    index.is_none()
+/// Builds the expression for an infix operator.
+fn infix(token: &Token, left: Expr, right: Expr) -> Result<Expr, Diagnostic> {
+    let span = left.span().to(right.span());
+    let op = match &token.token_type {
+        TokenType::Semicolon => return attach_else(left, right),
+        TokenType::Colon => return Ok(Expr::Conditional { condition: Box::new(left), value: Box::new(right), otherwise: None, span }),
+        TokenType::Underscore => return Ok(Expr::Index { set: Box::new(left), index: Box::new(right), span }),
+        TokenType::Plus => BinaryOp::Add,
+        TokenType::Dash => BinaryOp::Subtract,
+        TokenType::Star => BinaryOp::Multiply,
+        TokenType::Slash => BinaryOp::Divide,
+        TokenType::Percantage => BinaryOp::Remainder,
+        TokenType::Carrot => BinaryOp::Power,
+        TokenType::And => BinaryOp::And,
+        TokenType::Or => BinaryOp::Or,
+        TokenType::Xor => BinaryOp::Xor,
+        other => match comparison(other) {
+            Some(op) => op,
+            None => return Err(Diagnostic::new(format!("Unexpected character '{}'", token.value)).at(token.span)),
+        },
+    };
+    Ok(Expr::Binary { op, left: Box::new(left), right: Box::new(right), span })
+}
+
+/// Returns the comparison a token stands for, if it is one.
+fn comparison(token_type: &TokenType) -> Option<BinaryOp> {
+    match token_type {
+        TokenType::Equal => Some(BinaryOp::Equal),
+        TokenType::NotEqual => Some(BinaryOp::NotEqual),
+        TokenType::GreaterThan => Some(BinaryOp::Greater),
+        TokenType::GreaterThanOrEqualTo => Some(BinaryOp::GreaterOrEqual),
+        TokenType::LessThan => Some(BinaryOp::Less),
+        TokenType::LessThanOrEqualTo => Some(BinaryOp::LessOrEqual),
+        _ => None,
+    }
+}
+
+/// Returns whether an operator compares its operands.
+pub fn is_comparison(op: BinaryOp) -> bool {
+    matches!(op, BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Greater | BinaryOp::GreaterOrEqual | BinaryOp::Less | BinaryOp::LessOrEqual)
+}
+
+/// Gives the last conditional in a chain such as `X > 0: 1; X < 0: -1` the else after `;`.
+fn attach_else(conditional: Expr, otherwise: Expr) -> Result<Expr, Diagnostic> {
+    match conditional {
+        Expr::Conditional { condition, value, otherwise: inner, span } => {
+            let span = span.to(otherwise.span());
+            let otherwise = match inner {
+                None => otherwise,
+                Some(inner) => attach_else(*inner, otherwise)?,
+            };
+            Ok(Expr::Conditional { condition, value, otherwise: Some(Box::new(otherwise)), span })
+        }
+        other => Err(Diagnostic::new("Expected a condition such as 'X > 0: X' before ';'").at(other.span())),
+    }
 }
 
-/// Prints the expression represented by the given ExprNode.
-/// 
+/// Prints the expression, with parentheses around every operation.
+///
 /// # Arguments
-/// * `node` - An ExprNode reference containing the expression to be printed.
-/// 
+/// * `expr` - The expression to be printed.
+///
 /// # Returns
 /// A String representing the printed expression.
-pub fn print_expr(node: &ExprNode) -> String {
-    if node.func_name.is_some() && node.func_parameters.is_some() {
-        let mut func_params: String = String::new();
-        for n in node.func_parameters.as_ref().unwrap() {
-            for o in n.iter() {
-                if let Some(p) = o.as_ref() {
-                    func_params = format!("{}, {}", func_params, print_expr(p));
-                }
-            }
+pub fn print_expr(expr: &Expr) -> String {
+    let list = |items: &[Expr]| items.iter().map(print_expr).collect::<Vec<String>>().join(", ");
+    match expr {
+        Expr::Number { value, .. } => value.to_string(),
+        Expr::Text { value, .. } => format!("{:?}", value),
+        Expr::Variable { name, .. } => name.clone(),
+        Expr::Binary { op, left, right, .. } => format!("({}{}{})", print_expr(left), op, print_expr(right)),
+        Expr::Chain { operands, ops, .. } => {
+            let chain: Vec<String> = ops.iter().zip(operands.iter().skip(1)).map(|(op, x)| format!("{}{}", op, print_expr(x))).collect();
+            format!("({}{})", print_expr(&operands[0]), chain.concat())
         }
+        Expr::Unary { op: UnaryOp::Factorial, operand, .. } => format!("({}!)", print_expr(operand)),
+        Expr::Unary { op, operand, .. } => format!("({}{})", op, print_expr(operand)),
+        Expr::Call { name, arguments, .. } => format!("{}({})", name, list(arguments)),
+        Expr::Set { elements, .. } => format!("{{{}}}", list(elements)),
+        Expr::Index { set, index, .. } => format!("({}_{})", print_expr(set), print_expr(index)),
+        Expr::Conditional { condition, value, otherwise, .. } => match otherwise {
+            Some(otherwise) => format!("({}: {}; {})", print_expr(condition), print_expr(value), print_expr(otherwise)),
+            None => format!("({}: {})", print_expr(condition), print_expr(value)),
+        },
+        Expr::Assign { value, name, .. } => format!("{} -> {}", print_expr(value), name),
+        Expr::FuncDef { name, parameters, body, .. } => format!("{}({}) => {}", name, parameters.join(", "), print_expr(body)),
+        Expr::Marker { mark, .. } => format!("${}", mark),
+        Expr::Import { path, namespace: Some(namespace), .. } => format!("import {:?} as {}", path, namespace),
+        Expr::Import { path, .. } => format!("import {:?}", path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::run_script;
+    use crate::lexer::get_lexer_lines;
+
+    /// Parses one line, returning its statements as `print_expr` writes them.
+    fn tree(line: &str) -> String {
+        let (lines, _) = get_lexer_lines(line, 0);
+        let (statements, diagnostics) = parse(lines);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        statements.iter().flatten().map(print_expr).collect::<Vec<_>>().join(", ")
+    }
 
-        return format!("{}({})", node.func_name.as_ref().unwrap(), func_params.trim_start_matches(", "));
+    fn run(script: &str) -> String {
+        run_script(script, "", Limits::default()).unwrap_or_else(|errors| panic!("{:?}", errors))
     }
 
-    if node.marker_line.is_some() {
-        return format!("${}", node.marker_num.as_ref().unwrap());
+    #[test]
+    fn operators_bind_by_precedence() {
+        assert_eq!(tree("1 + 2 * 3 - 4"), tree("(1 + (2 * 3)) - 4"));
+        assert_eq!(tree("2^3^2"), tree("2^(3^2)"));
+        assert_eq!(tree("1/2X"), tree("1/(2*X)"));
+        assert_eq!(tree("X_2^2"), tree("(X_2)^2"));
+        assert_eq!(run("displayln(2^3^2, \" \", 3!^2, \" \", 10 - 2 - 3)"), "512 36 5\n");
     }
 
-    if node.operand1.is_none() && node.operand2.is_none() {
-        return node.c.to_string();
+    #[test]
+    fn unary_operators_apply_to_any_expression() {
+        assert_eq!(run("displayln(-2^2, \" \", -(2 + 3), \" \", 2 * -3, \" \", +4, \" \", --1)"), "-4 -5 -6 4 1\n");
+        assert_eq!(run("f(X) => X * 10\ndisplayln(-f(2), \" \", -{1, 2}_2)"), "-20 -2\n");
+        assert_eq!(tree("not X > 2 and Y"), tree("(not (X > 2)) and Y"));
     }
 
-    let left = node.operand1.as_ref().map_or("".to_string(), |n| print_expr(n));
-    let right = node.operand2.as_ref().map_or("".to_string(), |n| print_expr(n));
+    #[test]
+    fn logical_operators_short_circuit() {
+        // the right operands would raise an error if evaluated
+        assert_eq!(run("displayln(0 and nothing(), 1 or nothing(), 1 xor 1, 0 || 1, 1 && 1, not 1)"), "010110\n");
+        let errors = run_script("displayln(2 and 1)", "", Limits::default()).unwrap_err();
+        assert_eq!(errors[0].message, "Expected the operands of and to have value 0 or 1, got 2");
+    }
+
+    #[test]
+    fn comparisons_chain_and_compare_sets() {
+        assert_eq!(run("5 -> X, displayln(0 < X < 10, 0 < X < 4, 1 != 2, 1 <> 1, 1 <= 1 < 2)"), "10101\n");
+        assert_eq!(run("displayln({1, 2} = {1, 2}, {1, 2} = {1, 2, 3}, {1, 2} != {1, 2, 3}, {1, 2} < {2, 3})"), "1011\n");
+        let errors = run_script("displayln({1, 2} < {1, 2, 3})", "", Limits::default()).unwrap_err();
+        assert_eq!(errors[0].message, "Expected sets of the same length with operator <, got 2 and 3 elements");
+    }
+
+    #[test]
+    fn statements_parse() {
+        assert_eq!(tree("1 -> A, f(X, Y) => X: Y; 0"), "1 -> A, f(X, Y) => (X: Y; 0)");
+        assert_eq!(tree("$3"), "$3");
+    }
+
+    #[test]
+    fn syntax_errors_are_all_reported() {
+        let script = "2 +* 3\nAb -> B\ndisplayln((1 + 2)\n\n4 -> C";
+        let errors = run_script(script, "", Limits::default()).unwrap_err();
+        let lines: Vec<i32> = errors.iter().flat_map(|x| x.span).map(|x| x.line).collect();
+        assert_eq!(lines, vec![1, 2, 3]);
+        assert_eq!(errors[2].message, "Expected ')' to close '('");
+    }
 
-    format!("({}{}{})", left, node.c, right)
+    #[test]
+    fn nesting_is_bounded() {
+        let max = Limits::default().nesting.unwrap();
+        let nested = |open: &str, n: usize| format!("{}1{}", open.repeat(n), ")".repeat(n));
+        assert_eq!(run(&format!("displayln({})", nested("1+(", max - 1))), format!("{}\n", max));
+        let errors = run_script(&nested("(", max * 10), "", Limits::default()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, format!("Expression nested more than {} deep", max));
+        assert_eq!(errors[0].code, Some("nesting-limit"));
+        // the rest of the line is still parsed after the statement nesting too deeply
+        let errors = run_script(&format!("displayln(abs({}), 2), )", nested("(", max)), "", Limits::default()).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[1].message, "Expected a value before ')'");
+        let (_, errors) = parse_with(get_lexer_lines(&nested("(", max + 1), 0).0, &Limits { nesting: Some(max + 1), ..Limits::none() });
+        assert!(errors.is_empty());
+    }
 }
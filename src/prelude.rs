@@ -0,0 +1,63 @@
+use crate::diagnostic::{add_file, raise_all};
+use crate::interpreter::{get_variables, interpret_with, Context, DefinedFunction, Variable};
+use crate::lexer::get_lexer_lines;
+use crate::limits::{Counter, Limits};
+use crate::parser::parse;
+use std::cell::RefCell;
+
+/// Constants and functions written in calc_lang, defined before every script and imported file.
+const PRELUDE: &str = include_str!("prelude.calc");
+
+/// Whether an engine starts scripts with the prelude, set with `Engine::set_prelude`, and the functions and constants
+/// it defines, kept after running it the first time.
+pub struct Prelude {
+    enabled: bool,
+    session: RefCell<Option<(Vec<DefinedFunction>, Vec<Variable>)>>
+}
+
+impl Prelude {
+    pub fn new(enabled: bool) -> Self {
+        Prelude { enabled, session: RefCell::new(None) }
+    }
+
+    /// Returns whether scripts start with the prelude.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+/// Returns the functions and variables a script starts with: the prelude's, marked as built-in so the script
+/// may redefine them, followed by the default variables.
+///
+/// # Arguments
+/// * `context` - The native functions the prelude can call and the engine's prelude. It runs without the limits of
+///   the run.
+pub fn session(context: &Context) -> (Vec<DefinedFunction>, Vec<Variable>) {
+    if !context.prelude.enabled {
+        return (vec![], get_variables());
+    }
+    // run without borrowing the cache, so an error in it leaves the next run to try again
+    let cached = context.prelude.session.borrow().clone();
+    let (functions, constants) = cached.unwrap_or_else(|| {
+        let prelude = run(context);
+        *context.prelude.session.borrow_mut() = Some(prelude.clone());
+        prelude
+    });
+    (functions, get_variables().into_iter().chain(constants).collect())
+}
+
+/// Runs the prelude, returning the functions and constants it defines marked as built-in.
+fn run(context: &Context) -> (Vec<DefinedFunction>, Vec<Variable>) {
+    let (lines, mut diagnostics) = get_lexer_lines(PRELUDE, add_file("<prelude>", PRELUDE));
+    let (lines, parser_diagnostics) = parse(lines);
+    diagnostics.extend(parser_diagnostics);
+    if !diagnostics.is_empty() {
+        raise_all(diagnostics);
+    }
+    let unlimited = Context { counter: Counter::new(Limits::none()), ..*context };
+    let (mut functions, variables) = interpret_with(lines, vec![], get_variables(), &unlimited);
+    let mut constants: Vec<Variable> = variables.into_iter().filter(|x| x.is_constant).collect();
+    functions.iter_mut().for_each(|x| x.is_builtin = true);
+    constants.iter_mut().for_each(|x| x.is_builtin = true);
+    (functions, constants)
+}
@@ -0,0 +1,148 @@
+use crate::interpreter::{NativeFunction, Value};
+use crate::stats::{binomial, deviation, normal_inverse, poisson, probability, rate, trials};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The body of a function drawing from the engine's random number generator.
+type RandomFunction = fn(&[Value], &mut StdRng) -> Value;
+
+/// Returns the functions drawing from an engine's random number generator, which `Engine::seed` and `seed()` reseed.
+pub fn get_random_functions(rng: &Rc<RefCell<StdRng>>) -> Vec<NativeFunction> {
+    let native = |name: &str, parameters: i32, execute: RandomFunction| {
+        let rng = rng.clone();
+        NativeFunction { name: name.to_string(), parameters, execute: Box::new(move |v| Ok(execute(v, &mut rng.borrow_mut()))) }
+    };
+    vec![
+        native("rand", 0, |_, rng| {
+            Value::Number(random(rng))
+        }),
+        native("randn", 0, |_, rng| {
+            Value::Number(normal(rng))
+        }),
+        native("randint", 2, |v, rng| {
+            let (low, high) = (v[0].number().ceil(), v[1].number().floor());
+            if low > high {
+                panic!("Expected an integer between {} and {} in function: randint", v[0].number(), v[1].number());
+            }
+            Value::Number(rng.gen_range(low as i64..=high as i64) as f64)
+        }),
+        native("choice", 1, |v, rng| {
+            let set = v[0].as_set().unwrap_or_else(|| panic!("Expected a set in function: choice"));
+            match set.choose(rng) {
+                Some(x) => Value::Number(*x),
+                None => panic!("Expected a non-empty set in function: choice"),
+            }
+        }),
+        native("shuffle", 1, |v, rng| {
+            let mut set = v[0].as_set().cloned().unwrap_or_else(|| panic!("Expected a set in function: shuffle"));
+            set.shuffle(rng);
+            Value::Set(set)
+        }),
+        native("seed", 1, |v, rng| {
+            *rng = StdRng::seed_from_u64(seed(v[0].number(), "seed"));
+            Value::Number(0.0)
+        }),
+        native("randnorm", 2, |v, rng| {
+            Value::Number(v[0].number() + deviation(v[1].number(), "randnorm") * normal(rng))
+        }),
+        native("randbinom", 2, |v, rng| {
+            let (n, p) = (trials(v[0].number(), "randbinom"), probability(v[1].number(), "randbinom"));
+            Value::Number(binomial(n, p).inverse(random(rng), "randbinom"))
+        }),
+        native("randpois", 1, |v, rng| {
+            let lambda = rate(v[0].number(), "randpois");
+            Value::Number(poisson(lambda).inverse(random(rng), "randpois"))
+        }),
+    ]
+}
+
+/// Checks that an argument is a seed, a whole number from 0 up to the largest one a generator takes.
+///
+/// # Arguments
+/// * `seed` - The seed, the same seed always producing the same sequence.
+/// * `name` - The name of the calling function, for error messages.
+fn seed(seed: f64, name: &str) -> u64 {
+    if !(0.0..=u64::MAX as f64).contains(&seed) || seed.fract() != 0.0 {
+        panic!("Expected a whole number seed of at least 0, got {} in function: {}", seed, name);
+    }
+    seed as u64
+}
+
+/// Draws a uniformly distributed number from 0 (inclusive) to 1 (exclusive).
+pub fn random(rng: &mut StdRng) -> f64 {
+    rng.gen::<f64>()
+}
+
+/// Draws a uniformly distributed number strictly between 0 and 1.
+pub fn open_random(rng: &mut StdRng) -> f64 {
+    loop {
+        let u = random(rng);
+        if u > 0.0 {
+            return u;
+        }
+    }
+}
+
+/// Draws a number from the standard normal distribution.
+pub fn normal(rng: &mut StdRng) -> f64 {
+    normal_inverse(open_random(rng))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diagnostic::add_file;
+    use crate::engine::{run_script, Engine};
+    use crate::io::MemoryIo;
+    use crate::lexer::get_lexer_lines;
+    use crate::limits::Limits;
+    use crate::parser::parse;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const DRAWS: &str = "displayln(rand(), randn(), randint(1, 100), choice({1, 2, 3}), shuffle({1, 2, 3, 4}), randnorm(0, 1), randbinom(10, 0.5), randpois(3))";
+
+    /// Runs a script with an engine, returning what it displayed.
+    fn run(engine: &mut Engine, script: &str) -> String {
+        let io = Rc::new(RefCell::new(MemoryIo::new("")));
+        engine.set_io(io.clone());
+        let (lines, _) = get_lexer_lines(script, add_file("<test>", script));
+        engine.run(parse(lines).0).unwrap();
+        let output = io.borrow().output.clone();
+        output
+    }
+
+    #[test]
+    fn seeded_engines_draw_the_same_numbers() {
+        let (mut first, mut second) = (Engine::new(), Engine::new());
+        first.seed(7);
+        second.seed(7);
+        let draws = run(&mut first, DRAWS);
+        // each engine has its own generator, so drawing from another one changes nothing
+        run(&mut Engine::new(), DRAWS);
+        assert_eq!(run(&mut second, DRAWS), draws);
+        first.seed(7);
+        assert_eq!(run(&mut first, DRAWS), draws);
+        first.seed(8);
+        assert_ne!(run(&mut first, DRAWS), draws);
+    }
+
+    #[test]
+    fn seed_reseeds_within_a_script() {
+        let script = format!("seed(42)\n{}\nseed(42)\n{}", DRAWS, DRAWS);
+        let output = run_script(&script, "", Limits::default()).unwrap();
+        let (first, second) = output.split_once('\n').unwrap();
+        assert_eq!(first.to_string() + "\n", second);
+        assert_eq!(run_script(&script, "", Limits::default()).unwrap(), output);
+    }
+
+    #[test]
+    fn seeds_are_whole_numbers_of_at_least_0() {
+        for seed in ["-3", "1.9", "1/0"] {
+            let errors = run_script(&format!("seed({})", seed), "", Limits::default()).unwrap_err();
+            assert!(errors[0].message.starts_with("Expected a whole number seed of at least 0"), "{}", errors[0].message);
+        }
+    }
+}
@@ -0,0 +1,548 @@
+use crate::interpreter::{PreDefinedFunction, Value};
+use std::f64::consts::{PI, SQRT_2};
+
+// Chebyshev coefficients for erfc, accurate to about 1.2e-16
+const ERFC_COEFFICIENTS: [f64; 28] = [
+    -1.302_653_719_781_709_4, 6.419_697_923_564_902e-1, 1.947_647_320_418_583_6e-2, -9.561_514_786_808_63e-3,
+    -9.465_953_444_820_36e-4, 3.668_394_978_527_61e-4, 4.252_332_480_690_7e-5, -2.027_857_811_253_4e-5,
+    -1.624_290_004_647e-6, 1.303_655_835_58e-6, 1.562_644_172_2e-8, -8.523_809_591_5e-8,
+    6.529_054_439e-9, 5.059_343_495e-9, -9.913_641_56e-10, -2.273_651_22e-10,
+    9.646_791_1e-11, 2.394_038e-12, -6.886_027e-12, 8.944_87e-13,
+    3.130_92e-13, -1.127_08e-13, 3.81e-16, 7.106e-15,
+    -1.523e-15, -9.4e-17, 1.21e-16, -2.8e-17
+];
+// Lanczos approximation coefficients for g = 7
+const LANCZOS_COEFFICIENTS: [f64; 9] = [
+    0.999_999_999_999_809_9, 676.520_368_121_885_1, -1_259.139_216_722_402_8, 771.323_428_777_653_1,
+    -176.615_029_162_140_6, 12.507_343_278_686_905, -0.138_571_095_265_720_12, 9.984_369_578_019_572e-6,
+    1.505_632_735_149_311_6e-7
+];
+
+/// The largest number of trials or mean of a discrete distribution, keeping the probabilities summed for it few enough
+/// to add up quickly.
+const MAX_COUNT: f64 = 1e10;
+/// The size, relative to the sum so far, of a probability too small to change it.
+const NEGLIGIBLE: f64 = 1e-30;
+
+/// Returns a vector of statistics functions over sets and probability distributions.
+pub fn get_statistics_functions() -> Vec<PreDefinedFunction> {
+    vec![
+        PreDefinedFunction {
+            name: "mean".to_string(),
+            parameters: 1,
+            execute: |v| {
+                Value::Number(mean(samples(&v[0], "mean")))
+            }
+        },
+        PreDefinedFunction {
+            name: "median".to_string(),
+            parameters: 1,
+            execute: |v| {
+                Value::Number(quantile(samples(&v[0], "median"), 0.5))
+            }
+        },
+        PreDefinedFunction {
+            name: "mode".to_string(),
+            parameters: 1,
+            execute: |v| {
+                Value::Number(mode(samples(&v[0], "mode")))
+            }
+        },
+        PreDefinedFunction {
+            name: "variance".to_string(),
+            parameters: 1,
+            execute: |v| {
+                Value::Number(variance(spread_samples(&v[0], "variance")))
+            }
+        },
+        PreDefinedFunction {
+            name: "stdev".to_string(),
+            parameters: 1,
+            execute: |v| {
+                Value::Number(variance(spread_samples(&v[0], "stdev")).sqrt())
+            }
+        },
+        PreDefinedFunction {
+            name: "quantile".to_string(),
+            parameters: 2,
+            execute: |v| {
+                if !(0.0..=1.0).contains(&v[1].number()) {
+                    panic!("Expected quantile between 0 and 1, got {} in function: quantile", v[1].number());
+                }
+                Value::Number(quantile(samples(&v[0], "quantile"), v[1].number()))
+            }
+        },
+        PreDefinedFunction {
+            name: "covariance".to_string(),
+            parameters: 2,
+            execute: |v| {
+                let (x, y) = paired_samples(&v[0], &v[1], "covariance");
+                Value::Number(covariance(x, y))
+            }
+        },
+        PreDefinedFunction {
+            name: "correlation".to_string(),
+            parameters: 2,
+            execute: |v| {
+                let (x, y) = paired_samples(&v[0], &v[1], "correlation");
+                Value::Number(covariance(x, y) / (variance(x) * variance(y)).sqrt())
+            }
+        },
+        PreDefinedFunction {
+            name: "regression".to_string(),
+            parameters: 2,
+            execute: |v| {
+                let (x, y) = paired_samples(&v[0], &v[1], "regression");
+                let slope = covariance(x, y) / variance(x);
+                let intercept = mean(y) - slope * mean(x);
+                Value::Set(vec![slope, intercept])
+            }
+        },
+        PreDefinedFunction {
+            name: "normpdf".to_string(),
+            parameters: 3,
+            execute: |v| {
+                let sigma = deviation(v[2].number(), "normpdf");
+                let z = (v[0].number() - v[1].number()) / sigma;
+                Value::Number((-0.5 * z * z).exp() / (sigma * (2.0 * PI).sqrt()))
+            }
+        },
+        PreDefinedFunction {
+            name: "normcdf".to_string(),
+            parameters: 3,
+            execute: |v| {
+                let sigma = deviation(v[2].number(), "normcdf");
+                Value::Number(normal_cdf((v[0].number() - v[1].number()) / sigma))
+            }
+        },
+        PreDefinedFunction {
+            name: "norminv".to_string(),
+            parameters: 3,
+            execute: |v| {
+                let sigma = deviation(v[2].number(), "norminv");
+                Value::Number(v[1].number() + sigma * normal_inverse(probability(v[0].number(), "norminv")))
+            }
+        },
+        PreDefinedFunction {
+            name: "binompdf".to_string(),
+            parameters: 3,
+            execute: |v| {
+                Value::Number(binomial_pdf(v[0].number(), trials(v[1].number(), "binompdf"), probability(v[2].number(), "binompdf")))
+            }
+        },
+        PreDefinedFunction {
+            name: "binomcdf".to_string(),
+            parameters: 3,
+            execute: |v| {
+                let (n, p) = (trials(v[1].number(), "binomcdf"), probability(v[2].number(), "binomcdf"));
+                Value::Number(binomial(n, p).cumulative(v[0].number()))
+            }
+        },
+        PreDefinedFunction {
+            name: "binominv".to_string(),
+            parameters: 3,
+            execute: |v| {
+                let (n, p) = (trials(v[1].number(), "binominv"), probability(v[2].number(), "binominv"));
+                Value::Number(binomial(n, p).inverse(probability(v[0].number(), "binominv"), "binominv"))
+            }
+        },
+        PreDefinedFunction {
+            name: "poisspdf".to_string(),
+            parameters: 2,
+            execute: |v| {
+                Value::Number(poisson_pdf(v[0].number(), rate(v[1].number(), "poisspdf")))
+            }
+        },
+        PreDefinedFunction {
+            name: "poisscdf".to_string(),
+            parameters: 2,
+            execute: |v| {
+                let lambda = rate(v[1].number(), "poisscdf");
+                Value::Number(poisson(lambda).cumulative(v[0].number()))
+            }
+        },
+        PreDefinedFunction {
+            name: "poissinv".to_string(),
+            parameters: 2,
+            execute: |v| {
+                let lambda = rate(v[1].number(), "poissinv");
+                Value::Number(poisson(lambda).inverse(probability(v[0].number(), "poissinv"), "poissinv"))
+            }
+        }
+    ]
+}
+
+/// Reads the elements of a set argument, which must not be empty.
+fn samples<'a>(value: &'a Value, name: &str) -> &'a [f64] {
+    match value {
+        Value::Set(set) if !set.is_empty() => set,
+        Value::Set(_) => panic!("Expected a non-empty set in function: {}", name),
+        other => panic!("Expected a set, got {} in function: {}", other.kind(), name),
+    }
+}
+
+/// Reads the elements of a set argument with at least two, as a sample variance needs.
+fn spread_samples<'a>(value: &'a Value, name: &str) -> &'a [f64] {
+    let x = samples(value, name);
+    if x.len() < 2 {
+        panic!("Expected a set of at least 2 values, got {} in function: {}", x.len(), name);
+    }
+    x
+}
+
+/// Reads two set arguments of the same length, with at least two elements each.
+fn paired_samples<'a>(x: &'a Value, y: &'a Value, name: &str) -> (&'a [f64], &'a [f64]) {
+    let (x, y) = (spread_samples(x, name), spread_samples(y, name));
+    if x.len() != y.len() {
+        panic!("Expected sets of the same length, got {} and {} in function: {}", x.len(), y.len(), name);
+    }
+    (x, y)
+}
+
+/// Checks that an argument is a probability between 0 and 1.
+pub(crate) fn probability(p: f64, name: &str) -> f64 {
+    if !(0.0..=1.0).contains(&p) {
+        panic!("Expected probability between 0 and 1, got {} in function: {}", p, name);
+    }
+    p
+}
+
+/// Checks that an argument is the standard deviation of a normal distribution, a finite number above 0.
+pub(crate) fn deviation(sigma: f64, name: &str) -> f64 {
+    if sigma <= 0.0 || !sigma.is_finite() {
+        panic!("Expected a standard deviation above 0, got {} in function: {}", sigma, name);
+    }
+    sigma
+}
+
+/// Checks that an argument is a number of trials, a whole number from 0 to `MAX_COUNT`.
+pub(crate) fn trials(n: f64, name: &str) -> f64 {
+    if !(0.0..=MAX_COUNT).contains(&n) || n.fract() != 0.0 {
+        panic!("Expected a whole number of trials between 0 and {:e}, got {} in function: {}", MAX_COUNT, n, name);
+    }
+    n
+}
+
+/// Checks that an argument is the mean of a Poisson process, a number from 0 to `MAX_COUNT`.
+pub(crate) fn rate(lambda: f64, name: &str) -> f64 {
+    if !(0.0..=MAX_COUNT).contains(&lambda) {
+        panic!("Expected a mean between 0 and {:e}, got {} in function: {}", MAX_COUNT, lambda, name);
+    }
+    lambda
+}
+
+/// Calculates the arithmetic mean of a sample.
+pub fn mean(x: &[f64]) -> f64 {
+    x.iter().sum::<f64>() / x.len() as f64
+}
+
+/// Calculates the sample variance, dividing by one less than the sample size.
+pub fn variance(x: &[f64]) -> f64 {
+    covariance(x, x)
+}
+
+/// Calculates the sample covariance of two samples of the same length.
+pub fn covariance(x: &[f64], y: &[f64]) -> f64 {
+    let (mean_x, mean_y) = (mean(x), mean(y));
+    let sum: f64 = x.iter().zip(y).map(|(a, b)| (a - mean_x) * (b - mean_y)).sum();
+    sum / (x.len() as f64 - 1.0)
+}
+
+/// Calculates a quantile by linear interpolation between the closest ranks.
+///
+/// # Arguments
+/// * `x` - The sample.
+/// * `p` - The quantile between 0 and 1, 0.5 being the median.
+pub fn quantile(x: &[f64], p: f64) -> f64 {
+    let mut sorted = x.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let rank = p * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (rank - lower as f64) * (sorted[upper] - sorted[lower])
+}
+
+/// Finds the most frequent value of a sample, preferring the smallest on ties.
+pub fn mode(x: &[f64]) -> f64 {
+    let mut sorted = x.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let (mut best, mut best_count) = (sorted[0], 0);
+    let mut start = 0;
+    for i in 1..=sorted.len() {
+        if i == sorted.len() || sorted[i] != sorted[start] {
+            if i - start > best_count {
+                best = sorted[start];
+                best_count = i - start;
+            }
+            start = i;
+        }
+    }
+    best
+}
+
+/// Calculates the complementary error function.
+pub fn erfc(x: f64) -> f64 {
+    if x < 0.0 {
+        return 2.0 - erfc(-x);
+    }
+    let t = 2.0 / (2.0 + x);
+    let ty = 4.0 * t - 2.0;
+    let (mut d, mut dd) = (0.0, 0.0);
+    for coefficient in ERFC_COEFFICIENTS.iter().skip(1).rev() {
+        let previous = d;
+        d = ty * d - dd + coefficient;
+        dd = previous;
+    }
+    t * (-x * x + 0.5 * (ERFC_COEFFICIENTS[0] + ty * d) - dd).exp()
+}
+
+/// Calculates the cumulative distribution function of the standard normal distribution.
+pub fn normal_cdf(z: f64) -> f64 {
+    0.5 * erfc(-z / SQRT_2)
+}
+
+/// Calculates the inverse of the standard normal cumulative distribution function.
+///
+/// Uses Acklam's rational approximation refined by one step of Halley's method.
+pub fn normal_inverse(p: f64) -> f64 {
+    const A: [f64; 6] = [-3.969_683_028_665_376e1, 2.209_460_984_245_205e2, -2.759_285_104_469_687e2, 1.383_577_518_672_69e2, -3.066_479_806_614_716e1, 2.506_628_277_459_239];
+    const B: [f64; 5] = [-5.447_609_879_822_406e1, 1.615_858_368_580_409e2, -1.556_989_798_598_866e2, 6.680_131_188_771_972e1, -1.328_068_155_288_572e1];
+    const C: [f64; 6] = [-7.784_894_002_430_293e-3, -3.223_964_580_411_365e-1, -2.400_758_277_161_838, -2.549_732_539_343_734, 4.374_664_141_464_968, 2.938_163_982_698_783];
+    const D: [f64; 4] = [7.784_695_709_041_462e-3, 3.224_671_290_700_398e-1, 2.445_134_137_142_996, 3.754_408_661_907_416];
+    const LOW: f64 = 0.02425;
+
+    if p <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+    let x = if !(LOW..=1.0 - LOW).contains(&p) {
+        let q = (-2.0 * p.min(1.0 - p).ln()).sqrt();
+        let x = (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5]) / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0);
+        if p < LOW { x } else { -x }
+    }
+    else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    };
+    let e = normal_cdf(x) - p;
+    let u = e * (2.0 * PI).sqrt() * (x * x / 2.0).exp();
+    x - u / (1.0 + x * u / 2.0)
+}
+
+/// Calculates the natural logarithm of the gamma function with the Lanczos approximation.
+pub fn ln_gamma(x: f64) -> f64 {
+    if x < 0.5 {
+        // reflection formula
+        return (PI / (PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let mut sum = LANCZOS_COEFFICIENTS[0];
+    for (i, coefficient) in LANCZOS_COEFFICIENTS.iter().enumerate().skip(1) {
+        sum += coefficient / (x + i as f64);
+    }
+    let t = x + 7.5;
+    0.5 * (2.0 * PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+/// Calculates the probability of `k` successes in `n` trials with success probability `p`.
+pub fn binomial_pdf(k: f64, n: f64, p: f64) -> f64 {
+    if k < 0.0 || k > n || k.fract() != 0.0 {
+        return 0.0;
+    }
+    if p == 0.0 || p == 1.0 {
+        return if (p == 0.0 && k == 0.0) || (p == 1.0 && k == n) { 1.0 } else { 0.0 };
+    }
+    let ln_choose = ln_gamma(n + 1.0) - ln_gamma(k + 1.0) - ln_gamma(n - k + 1.0);
+    (ln_choose + k * p.ln() + (n - k) * (1.0 - p).ln()).exp()
+}
+
+/// Calculates the probability of `k` events of a Poisson process with mean `lambda`.
+pub fn poisson_pdf(k: f64, lambda: f64) -> f64 {
+    if k < 0.0 || k.fract() != 0.0 {
+        return 0.0;
+    }
+    if lambda == 0.0 {
+        return if k == 0.0 { 1.0 } else { 0.0 };
+    }
+    (k * lambda.ln() - lambda - ln_gamma(k + 1.0)).exp()
+}
+
+/// A discrete distribution over the counts from 0 to `max`.
+pub(crate) struct Discrete<F: Fn(f64) -> f64> {
+    pdf: F,
+    mean: f64,
+    sd: f64,
+    skewness: f64,
+    max: f64
+}
+
+/// Returns the binomial distribution of `n` trials with success probability `p`.
+pub(crate) fn binomial(n: f64, p: f64) -> Discrete<impl Fn(f64) -> f64> {
+    let sd = (n * p * (1.0 - p)).sqrt();
+    Discrete { pdf: move |k| binomial_pdf(k, n, p), mean: n * p, sd, skewness: (1.0 - 2.0 * p) / sd, max: n }
+}
+
+/// Returns the Poisson distribution with mean `lambda`.
+pub(crate) fn poisson(lambda: f64) -> Discrete<impl Fn(f64) -> f64> {
+    Discrete { pdf: move |k| poisson_pdf(k, lambda), mean: lambda, sd: lambda.sqrt(), skewness: 1.0 / lambda.sqrt(), max: f64::INFINITY }
+}
+
+impl<F: Fn(f64) -> f64> Discrete<F> {
+    /// Sums the probabilities of the counts from 0 up to `k`, adding up the tail on the side of `k` away from the
+    /// mean so only the counts whose probabilities are not negligible are visited.
+    fn cumulative(&self, k: f64) -> f64 {
+        let k = k.floor();
+        if k < 0.0 {
+            return 0.0;
+        }
+        if k >= self.max {
+            return 1.0;
+        }
+        if k < self.mean { self.tail(k, -1.0).min(1.0) } else { (1.0 - self.tail(k + 1.0, 1.0)).max(0.0) }
+    }
+
+    /// Sums the probabilities from `from` in steps of `step` away from the mean, where they only get smaller,
+    /// stopping once they are negligible.
+    fn tail(&self, from: f64, step: f64) -> f64 {
+        let mut sum = 0.0;
+        let mut k = from;
+        while (0.0..=self.max).contains(&k) {
+            let p = (self.pdf)(k);
+            sum += p;
+            if p <= sum * NEGLIGIBLE || p.is_nan() {
+                break;
+            }
+            k += step;
+        }
+        sum
+    }
+
+    /// Finds the smallest count whose cumulative probability reaches `q`, searching out from the normal approximation
+    /// corrected for skewness.
+    ///
+    /// # Arguments
+    /// * `q` - The cumulative probability to reach.
+    /// * `name` - The name of the calling function, for error messages.
+    pub(crate) fn inverse(&self, q: f64, name: &str) -> f64 {
+        if q == 0.0 {
+            return 0.0;
+        }
+        if q == 1.0 {
+            // every count below the largest falls short of 1, unless all of the probability is at the mean
+            return if self.sd == 0.0 { self.mean } else { self.max };
+        }
+        let z = normal_inverse(q);
+        let guess = self.mean + self.sd * (z + self.skewness * (z * z - 1.0) / 6.0);
+        let guess = if guess.is_finite() { guess.round().clamp(0.0, self.max) } else { self.mean.round() };
+        if self.cumulative(guess).is_nan() {
+            panic!("Probability is not a number at {} in function: {}", guess, name);
+        }
+        let reaches = |k: f64| self.cumulative(k) >= q;
+
+        // widen the gap from the guess in doubling steps until the count found lies between `below` and `above`,
+        // then halve it, -1 falling short of any `q`
+        let (mut below, mut above) = (guess - 1.0, guess);
+        let mut step = 1.0;
+        if reaches(guess) {
+            while below >= 0.0 && reaches(below) {
+                above = below;
+                step *= 2.0;
+                below = (above - step).max(-1.0);
+            }
+        }
+        else {
+            below = guess;
+            above = (guess + 1.0).min(self.max);
+            while !reaches(above) {
+                below = above;
+                step *= 2.0;
+                above = (below + step).min(self.max);
+            }
+        }
+        while above - below > 1.0 {
+            let middle = ((below + above) / 2.0).floor();
+            if reaches(middle) { above = middle } else { below = middle }
+        }
+        above
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::run_script;
+    use crate::limits::Limits;
+
+    fn close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance, "{} is not {}", actual, expected);
+    }
+
+    #[test]
+    fn normal_distribution_matches_tables() {
+        close(normal_cdf(0.0), 0.5, 1e-16);
+        close(normal_cdf(1.96), 0.975_002_104_851_780, 1e-15);
+        close(normal_cdf(-3.0), 0.001_349_898_031_630_095, 1e-17);
+        close(erfc(0.5), 0.479_500_122_186_953_5, 1e-15);
+        close(normal_inverse(0.975), 1.959_963_984_540_054, 1e-14);
+        close(normal_inverse(1e-10), -6.361_340_902_404_056, 1e-12);
+        assert_eq!(normal_inverse(0.0), f64::NEG_INFINITY);
+        for p in [1e-300, 0.01, 0.3, 0.5, 0.8, 0.999] {
+            close(normal_cdf(normal_inverse(p)), p, p * 1e-12);
+        }
+    }
+
+    #[test]
+    fn discrete_probabilities_match_exact_values() {
+        close(binomial_pdf(3.0, 10.0, 0.5), 120.0 / 1024.0, 1e-15);
+        assert_eq!(binomial_pdf(2.5, 10.0, 0.5), 0.0);
+        assert_eq!(binomial_pdf(10.0, 10.0, 1.0), 1.0);
+        close(poisson_pdf(2.0, 3.0), 4.5 * (-3.0f64).exp(), 1e-15);
+        close(ln_gamma(10.0), 362_880f64.ln(), 1e-13);
+        close(ln_gamma(0.5), PI.sqrt().ln(), 1e-14);
+    }
+
+    #[test]
+    fn sample_statistics() {
+        let x = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert_eq!(mean(&x), 5.0);
+        close(variance(&x), 32.0 / 7.0, 1e-15);
+        assert_eq!(quantile(&x, 0.5), 4.5);
+        assert_eq!(quantile(&x, 0.25), 4.0);
+        assert_eq!(quantile(&[3.0, 1.0], 0.75), 2.5);
+        assert_eq!(mode(&x), 4.0);
+        assert_eq!(mode(&[3.0, 1.0, 3.0, 1.0]), 1.0);
+        let output = run_script("displayln(regression({1, 2, 3, 4}, {3, 5, 7, 9}), \" \", correlation({1, 2, 3}, {3, 2, 1}))", "", Limits::default());
+        assert_eq!(output.unwrap(), "{2, 1} -1\n");
+    }
+
+    #[test]
+    fn normal_functions_need_a_standard_deviation_above_0() {
+        let output = run_script("displayln(normpdf(1, 1, 2), \" \", normcdf(3, 1, 2), \" \", norminv(0.5, 1, 2))", "", Limits::default());
+        assert_eq!(output.unwrap(), format!("{} {} {}\n", 1.0 / (2.0 * (2.0 * PI).sqrt()), normal_cdf(1.0), 1.0 + 2.0 * normal_inverse(0.5)));
+        for call in ["normpdf(1, 0, 0)", "normcdf(1, 0, -1)", "norminv(0.5, 0, 0)", "randnorm(0, -2)", "normpdf(1, 0, 0/0)"] {
+            let errors = run_script(call, "", Limits::default()).unwrap_err();
+            assert!(errors[0].message.starts_with("Expected a standard deviation above 0"), "{}: {}", call, errors[0].message);
+        }
+    }
+
+    #[test]
+    fn discrete_sums_visit_only_the_counts_that_matter() {
+        assert_eq!(poisson(3.0).cumulative(1e13), 1.0);
+        assert_eq!(poisson(1e10).cumulative(0.0), 0.0);
+        assert_eq!(binomial(10.0, 0.1).cumulative(10.0), 1.0);
+        assert!((binomial(10.0, 0.5).cumulative(3.0) - 0.171875).abs() < 1e-15);
+        // the quantile found from the normal approximation is the one found counting up from 0
+        for (distribution, max) in [(binomial(1.0, 0.5), 1.0), (binomial(10.0, 0.3), 10.0), (binomial(5000.0, 0.001), 5000.0)] {
+            for q in [0.001, 0.3, 0.5, 0.9, 0.999999] {
+                let counted = (0..).map(|k| k as f64).find(|k| distribution.cumulative(*k) >= q || *k >= max).unwrap();
+                assert_eq!(distribution.inverse(q, "test"), counted);
+            }
+        }
+        assert_eq!(poisson(3.0).inverse(1.0, "test"), f64::INFINITY);
+        assert_eq!(binomial(10.0, 0.0).inverse(1.0, "test"), 0.0);
+        assert_eq!(poisson(1e10).inverse(0.5, "test"), 1e10);
+    }
+}
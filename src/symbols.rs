@@ -0,0 +1,225 @@
+use crate::diagnostic::{raise, raise_all, Diagnostic};
+use crate::lexer::{Lexer, Token, TokenType};
+
+/// Unicode symbols and the ASCII they stand for. The lexer reads each symbol as the tokens of its ASCII form.
+pub const ALIASES: [(char, &str); 7] = [
+    ('π', "pi"),
+    ('×', "*"),
+    ('÷', "/"),
+    ('≤', "<="),
+    ('≥', ">="),
+    ('≠', "!="),
+    ('∑', "summation")
+];
+/// Square root of the value right after it, `√X` being `sqrt(X)`.
+pub const ROOT: char = '√';
+/// Superscript minus, starting a negative power such as `X⁻¹`.
+pub const SUPERSCRIPT_MINUS: char = '⁻';
+const SUPERSCRIPT_DIGITS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+
+/// Returns the ASCII a Unicode symbol stands for.
+pub fn alias(c: char) -> Option<&'static str> {
+    ALIASES.iter().find(|(symbol, _)| *symbol == c).map(|(_, ascii)| *ascii)
+}
+
+/// Returns the digit a superscript digit stands for, such as `2` for `²`.
+pub fn superscript_digit(c: char) -> Option<char> {
+    SUPERSCRIPT_DIGITS.iter().position(|x| *x == c).map(|i| (b'0' + i as u8) as char)
+}
+
+/// Which symbols `calc_lang --convert ascii|unicode` writes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Notation {
+    Ascii, Unicode
+}
+
+/// Rewrites a script with either ASCII operators and names or their Unicode symbols, keeping spacing and comments.
+/// The converted script reads as the same tokens, so it runs the same.
+///
+/// # Arguments
+/// * `source` - The contents of the script, the file run.
+/// * `notation` - The symbols to write.
+///
+/// # Returns
+/// The converted contents.
+pub fn convert(source: &str, notation: Notation) -> String {
+    let mut lexer = Lexer::new(source, 0);
+    let tokens: Vec<Token> = lexer.by_ref().collect();
+    let errors = lexer.into_errors();
+    if !errors.is_empty() {
+        raise_all(errors);
+    }
+    convert_tokens(source, &tokens, 0..source.len(), notation)
+}
+
+/// Converts the part of the source in `range`, whose tokens are `tokens`.
+fn convert_tokens(source: &str, tokens: &[Token], range: std::ops::Range<usize>, notation: Notation) -> String {
+    let mut output = String::new();
+    // end of the source copied or replaced so far
+    let mut copied = range.start;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let token = &tokens[i];
+        let (replacement, used) = match notation {
+            Notation::Unicode => to_unicode(source, &tokens[i..]),
+            Notation::Ascii => to_ascii(source, &tokens[i..]),
+        };
+        if let Some(replacement) = replacement {
+            let start = token.span.offset;
+            let end = tokens[i + used - 1].span.offset + tokens[i + used - 1].span.length;
+            output += &source[copied..start];
+            // keep names from running into each other, such as "2πR" becoming "2 pi R"
+            let joins = |c: Option<char>| c.is_some_and(char::is_alphabetic);
+            if joins(output.chars().last()) && joins(replacement.chars().next()) {
+                output.push(' ');
+            }
+            output += &replacement;
+            if joins(replacement.chars().last()) && joins(source[end..].chars().next()) {
+                output.push(' ');
+            }
+            copied = end;
+        }
+        // a superscript run is several tokens sharing one span
+        i += used;
+        while i < tokens.len() && tokens[i].span.offset < copied {
+            i += 1;
+        }
+    }
+    output + &source[copied..range.end]
+}
+
+/// Returns the Unicode form of the tokens starting a slice, if they have one, and how many tokens it replaces.
+fn to_unicode(source: &str, tokens: &[Token]) -> (Option<String>, usize) {
+    let text = |token: &Token| &source[token.span.offset..token.span.offset + token.span.length];
+    let token = &tokens[0];
+    let symbol = match token.token_type {
+        TokenType::Star => Some("×".to_string()),
+        TokenType::Slash => Some("÷".to_string()),
+        TokenType::LessThanOrEqualTo => Some("≤".to_string()),
+        TokenType::GreaterThanOrEqualTo => Some("≥".to_string()),
+        TokenType::NotEqual => Some("≠".to_string()),
+        TokenType::Lowercase if text(token) == "pi" => Some("π".to_string()),
+        TokenType::Lowercase if text(token) == "summation" => Some("∑".to_string()),
+        // only a call with one argument, as √ takes a single value
+        TokenType::Lowercase if text(token) == "sqrt" && tokens.get(1).is_some_and(|x| x.token_type == TokenType::OpenParen) => {
+            let close = matching(tokens, 1);
+            let top_level_comma = tokens[1..close.unwrap_or(0)].iter().scan(0, |depth, x| {
+                match x.token_type {
+                    TokenType::OpenParen | TokenType::OpenCurley => *depth += 1,
+                    TokenType::CloseParen | TokenType::CloseCurley => *depth -= 1,
+                    _ => {}
+                }
+                Some(*depth == 1 && x.token_type == TokenType::Comma)
+            }).any(|x| x);
+            (close.is_some() && !top_level_comma).then(|| ROOT.to_string())
+        }
+        TokenType::Carrot => {
+            let negative = tokens.get(1).is_some_and(|x| x.token_type == TokenType::Dash);
+            let power = tokens.get(1 + usize::from(negative)).filter(|x| x.token_type == TokenType::Number && text(x).chars().all(|c| c.is_ascii_digit()));
+            if let Some(power) = power {
+                let digits: String = text(power).chars().map(|c| SUPERSCRIPT_DIGITS[c as usize - '0' as usize]).collect();
+                let minus = if negative { SUPERSCRIPT_MINUS.to_string() } else { String::new() };
+                return (Some(minus + &digits), 2 + usize::from(negative));
+            }
+            None
+        }
+        _ => None,
+    };
+    (symbol, 1)
+}
+
+/// Returns the ASCII form of the tokens starting a slice, if they were written as Unicode, and how many tokens it replaces.
+///
+/// # Panics
+/// If `√` is not followed by a value it can be written as `sqrt` of, such as another `√`.
+fn to_ascii(source: &str, tokens: &[Token]) -> (Option<String>, usize) {
+    let text = |token: &Token| &source[token.span.offset..token.span.offset + token.span.length];
+    let token = &tokens[0];
+    let Some(first) = text(token).chars().next() else {
+        return (None, 1);
+    };
+    if let Some(ascii) = alias(first) {
+        return (Some(ascii.to_string()), 1);
+    }
+    if first == SUPERSCRIPT_MINUS || superscript_digit(first).is_some() {
+        let power: String = text(token).chars().map(|c| superscript_digit(c).unwrap_or('-')).collect();
+        return (Some(format!("^{}", power)), 1);
+    }
+    if token.token_type != TokenType::Root {
+        return (None, 1);
+    }
+    // √ applies to one value: a parenthesised group keeps its parentheses, anything else gains them
+    let end = match tokens.get(1).map(|x| &x.token_type) {
+        Some(TokenType::OpenParen) => return (Some("sqrt".to_string()), 1),
+        Some(TokenType::Lowercase) if tokens.get(2).is_some_and(|x| x.token_type == TokenType::OpenParen) => matching(tokens, 2),
+        Some(TokenType::OpenCurley) => matching(tokens, 1),
+        Some(TokenType::Number | TokenType::Uppercase | TokenType::Lowercase) => Some(1),
+        _ => None,
+    };
+    let Some(end) = end else {
+        let hint = Some("write it as sqrt(...) with the value in parentheses".to_string());
+        raise(Diagnostic::new("Expected a number, name, call or bracket after '√' to convert it to ASCII").at(token.span).with_hint(hint))
+    };
+    let range = tokens[1].span.offset..tokens[end].span.offset + tokens[end].span.length;
+    (Some(format!("sqrt({})", convert_tokens(source, &tokens[1..=end], range, Notation::Ascii))), end + 1)
+}
+
+/// Returns the index of the bracket closing the one at `open`.
+fn matching(tokens: &[Token], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token.token_type {
+            TokenType::OpenParen | TokenType::OpenCurley => depth += 1,
+            TokenType::CloseParen | TokenType::CloseCurley => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return Some(i);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::run_script;
+    use crate::limits::Limits;
+
+    const SCRIPT: &str = "displayln(2sqrt(4), \" \", 3sqrt(9)sqrt(16), \" \", 2^2sqrt(4), \" \", X*2 <= 4 and not pi > 3)\n\
+        1 + 2 -> X, 2 -> R, displayln(R*X, \" \", pi*R^2, \" \", 2pi)\n\
+        f(X) => X^-1 != 2, displayln(f(2), \" \", tau/2 >= pi)\n";
+
+    /// Runs a script, returning everything it displayed.
+    fn run(script: &str) -> String {
+        run_script(script, "", Limits::default()).unwrap_or_else(|errors| panic!("{:?} in:\n{}", errors, script))
+    }
+
+    #[test]
+    fn converted_scripts_run_the_same() {
+        let unicode = convert(SCRIPT, Notation::Unicode);
+        let ascii = convert(&unicode, Notation::Ascii);
+        assert_ne!(unicode, SCRIPT);
+        let expected = run(SCRIPT);
+        assert_eq!(run(&unicode), expected);
+        assert_eq!(run(&ascii), expected);
+    }
+
+    #[test]
+    fn root_after_number_multiplies() {
+        assert_eq!(convert("2sqrt(4)", Notation::Unicode), "2√(4)");
+        assert_eq!(run("displayln(2√(4), \" \", 3√9√16)"), "4 36\n");
+    }
+
+    #[test]
+    fn roots_convert_to_ascii_or_fail() {
+        assert_eq!(convert("√X + √abs(-4) + √{1, √4}", Notation::Ascii), "sqrt(X) + sqrt(abs(-4)) + sqrt({1, sqrt(4)})");
+        for source in ["√-4", "√√16", "1 + √", "{1, √+2}"] {
+            let panic = std::panic::catch_unwind(|| convert(source, Notation::Ascii)).unwrap_err();
+            let diagnostic = panic.downcast::<Diagnostic>().unwrap();
+            assert!(diagnostic.message.starts_with("Expected a number, name, call or bracket after '√'"), "{}", source);
+            assert_eq!(source[diagnostic.span.unwrap().offset..].chars().next(), Some(ROOT), "{}", source);
+        }
+    }
+}